@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Cell representation the server evaluates the grid with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Backend {
+    /// One `FheUint8` per cell, see `server::Server`
+    Integer,
+    /// One `FheBool` per cell, see `bool_server::BoolServer`
+    Boolean,
+    /// One shortint `Ciphertext` per cell, see `shortint_server::ShortintServer`
+    Shortint,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "integer" => Ok(Backend::Integer),
+            "boolean" => Ok(Backend::Boolean),
+            "shortint" => Ok(Backend::Shortint),
+            _ => Err(format!(
+                "unknown backend `{s}`, expected `integer`, `boolean` or `shortint`"
+            )),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use crate::boundary::Boundary;
+use crate::deploy::Sizes;
+use crate::phases::Phases;
+use crate::rule::Rule;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Write as _};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// One harness case
+///
+/// Rule, boundary and seed are optional in a case file, the command line
+/// options apply to cases that leave them out.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Case {
+    pub(crate) m: u32,
+    pub(crate) n: u32,
+    pub(crate) steps: u32,
+    /// Median time in seconds a case may take to score
+    pub(crate) threshold: f64,
+    pub(crate) score: u32,
+    #[serde(default, deserialize_with = "parse")]
+    pub(crate) rule: Option<Rule>,
+    #[serde(default, deserialize_with = "parse")]
+    pub(crate) boundary: Option<Boundary>,
+    pub(crate) seed: Option<u64>,
+}
+
+impl From<(u32, u32, u32, f64, u32)> for Case {
+    fn from((m, n, steps, threshold, score): (u32, u32, u32, f64, u32)) -> Self {
+        Case {
+            m,
+            n,
+            steps,
+            threshold,
+            score,
+            rule: None,
+            boundary: None,
+            seed: None,
+        }
+    }
+}
+
+/// Rules and boundaries are written the way the command line takes them,
+/// e.g. `"B36/S23"` and `"torus"`
+fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CaseFile {
+    cases: Vec<Case>,
+}
+
+/// Load cases from a TOML or JSON file, told apart by its extension
+///
+/// Both hold a `cases` array, i.e. `[[cases]]` tables in TOML and
+/// `{"cases": [...]}` in JSON.
+pub(crate) fn load_cases(path: &Path) -> Result<Vec<Case>, Box<dyn Error>> {
+    let context = |e: &dyn Display| format!("failed to read {}: {e}", path.display());
+    let text = fs::read_to_string(path).map_err(|e| context(&e))?;
+    let file: CaseFile = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&text).map_err(|e| context(&e))?,
+        Some("json") => serde_json::from_str(&text).map_err(|e| context(&e))?,
+        _ => return Err(context(&"expected a .toml or .json case file").into()),
+    };
+    for (i, case) in file.cases.iter().enumerate() {
+        if case.m == 0 || case.n == 0 {
+            let empty = format!("case {i} is an empty {}x{} grid", case.m, case.n);
+            return Err(context(&empty).into());
+        }
+    }
+    Ok(file.cases)
+}
+
+/// Minimum, median and maximum of the measured runs, in seconds
+#[derive(Clone, Copy, Debug, Serialize)]
+pub(crate) struct Timings {
+    pub(crate) min: f64,
+    pub(crate) median: f64,
+    pub(crate) max: f64,
+}
+
+impl Timings {
+    /// `None` if there are no measured runs
+    pub(crate) fn new(mut durations: Vec<f64>) -> Option<Self> {
+        durations.sort_by(f64::total_cmp);
+        let (&min, &max) = (durations.first()?, durations.last()?);
+        let middle = durations.len() / 2;
+        let median = if durations.len().is_multiple_of(2) {
+            (durations[middle - 1] + durations[middle]) / 2.0
+        } else {
+            durations[middle]
+        };
+        Some(Timings { min, median, max })
+    }
+}
+
+/// What the harness found for one case
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct CaseReport {
+    pub(crate) case: usize,
+    pub(crate) m: u32,
+    pub(crate) n: u32,
+    pub(crate) steps: u32,
+    pub(crate) rule: String,
+    pub(crate) boundary: String,
+    /// `None` when the case starts from a pattern
+    pub(crate) seed: Option<u64>,
+    pub(crate) runs: u32,
+    #[serde(flatten)]
+    pub(crate) timings: Timings,
+    /// Mean of the measured runs
+    pub(crate) phases: Phases,
+    pub(crate) threshold: f64,
+    /// Whether every run decrypted to the expected grid
+    pub(crate) verified: bool,
+    /// Whether the case was verified within its threshold
+    pub(crate) pass: bool,
+    pub(crate) score: u32,
+    pub(crate) key_bytes: Option<usize>,
+    pub(crate) grid_bytes: Option<usize>,
+    pub(crate) result_bytes: Option<usize>,
+}
+
+impl CaseReport {
+    pub(crate) fn set_sizes(&mut self, sizes: Option<Sizes>) {
+        self.key_bytes = sizes.map(|s| s.key);
+        self.grid_bytes = sizes.map(|s| s.grid);
+        self.result_bytes = sizes.map(|s| s.result);
+    }
+}
+
+/// Everything a harness run found
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Report {
+    pub(crate) backend: String,
+    /// TFHE parameter set of the integer and boolean keys
+    pub(crate) parameters: String,
+    pub(crate) threads: usize,
+    pub(crate) warmup: u32,
+    pub(crate) cases: Vec<CaseReport>,
+    pub(crate) score: u32,
+    pub(crate) max_score: u32,
+}
+
+impl Report {
+    /// Write the report as JSON or as CSV, told apart by the extension of
+    /// `path`, the CSV has one line per case
+    pub(crate) fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let text = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::to_string_pretty(self)?,
+            Some("csv") => self.to_csv(),
+            _ => return Err(format!("{}: expected a .json or .csv report", path.display()).into()),
+        };
+        fs::write(path, text).map_err(|e| format!("failed to write {}: {e}", path.display()).into())
+    }
+
+    fn to_csv(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let mut csv = String::from(
+            "case,m,n,steps,rule,boundary,seed,runs,min,median,max,keygen,encrypt,evaluate,\
+             decrypt,verify,generations,allocations,copied_bytes,idle,idle_removed_lower_bound,threshold,verified,pass,score,key_bytes,grid_bytes,\
+             result_bytes\n",
+        );
+        for case in &self.cases {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                case.case,
+                case.m,
+                case.n,
+                case.steps,
+                case.rule,
+                case.boundary,
+                optional(case.seed.map(|s| s.to_string())),
+                case.runs,
+                case.timings.min,
+                case.timings.median,
+                case.timings.max,
+                case.phases.keygen,
+                case.phases.encrypt,
+                case.phases.evaluate,
+                case.phases.decrypt,
+                case.phases.verify,
+                // Seconds of every generation, in order
+                case.phases
+                    .generations
+                    .iter()
+                    .map(f64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                // Per generation as well
+                case.phases
+                    .traffic
+                    .iter()
+                    .map(|traffic| traffic.allocations.to_string())
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                case.phases
+                    .traffic
+                    .iter()
+                    .map(|traffic| traffic.copied.to_string())
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                optional(case.phases.idle.map(|idle| idle.pipelined.to_string())),
+                optional(case.phases.idle.map(|idle| idle.removed().to_string())),
+                case.threshold,
+                case.verified,
+                case.pass,
+                case.score,
+                optional(case.key_bytes.map(|b| b.to_string())),
+                optional(case.grid_bytes.map(|b| b.to_string())),
+                optional(case.result_bytes.map(|b| b.to_string())),
+            );
+        }
+        csv
+    }
+}
@@ -0,0 +1,101 @@
+use crate::cell_server::{CellServer, CellUpdate};
+use crate::circuit::{self, BitOps, Circuit, FheBoolOps};
+use crate::client::EncryptedGrid;
+use crate::grid::Grid;
+use crate::rule::Rule;
+use crate::server::{plain_thread_pool, thread_pool};
+use std::collections::HashMap;
+use tfhe::ServerKey;
+use tfhe::shortint::ServerKey as ShortintServerKey;
+
+/// Server that keeps every cell as a single encrypted bit
+///
+/// Each cell is updated by evaluating the compiled circuit of `circuit::life`
+/// gate by gate. Cells on a dead border get their own copy of the circuit
+/// with the missing neighbors folded away.
+pub(crate) type BoolServer<B = FheBoolOps> = CellServer<BoolUpdate<B>>;
+
+/// The cell update of a `BoolServer`
+pub(crate) struct BoolUpdate<B: BitOps> {
+    ops: B,
+    /// Compiled circuit by mask of the neighbors beyond a dead border
+    circuits: HashMap<u8, Circuit>,
+}
+
+impl BoolServer {
+    /// Create a server evaluating on `threads` worker threads,
+    /// or on every available core if `threads` is 0
+    pub(crate) fn new(
+        server_key: ServerKey,
+        grid: EncryptedGrid<tfhe::FheBool>,
+        rule: Rule,
+        threads: usize,
+    ) -> Self {
+        let pool = thread_pool(server_key, threads);
+        let update = BoolUpdate::new(FheBoolOps, &grid, rule);
+        CellServer::with_update(update, pool, grid)
+    }
+}
+
+impl BoolServer<ShortintServerKey> {
+    /// Evaluate the circuit on shortint ciphertexts holding 0 or 1, one
+    /// bootstrap per binary gate instead of the single lookup table of
+    /// `ShortintServer`
+    pub(crate) fn shortint(
+        server_key: ShortintServerKey,
+        grid: EncryptedGrid<tfhe::shortint::Ciphertext>,
+        rule: Rule,
+        threads: usize,
+    ) -> Self {
+        let update = BoolUpdate::new(server_key, &grid, rule);
+        CellServer::with_update(update, plain_thread_pool(threads), grid)
+    }
+}
+
+impl<B: BitOps> BoolUpdate<B> {
+    fn new(ops: B, grid: &EncryptedGrid<B::Bit>, rule: Rule) -> Self {
+        let life = circuit::life(rule);
+        let (m, n) = grid.dimensions();
+        let mut circuits = HashMap::new();
+        for x in 0..m {
+            for y in 0..n {
+                let missing = missing_neighbors(grid, x, y);
+                circuits.entry(missing).or_insert_with(|| {
+                    let bindings: Vec<(usize, bool)> = (0..8)
+                        .filter(|i| missing & (1 << i) != 0)
+                        .map(|i| (i, false))
+                        .collect();
+                    life.bind(&bindings).optimize()
+                });
+            }
+        }
+
+        BoolUpdate { ops, circuits }
+    }
+}
+
+impl<B: BitOps> CellUpdate for BoolUpdate<B> {
+    type Cell = B::Bit;
+
+    fn update_cell(&self, x: usize, y: usize, grid: &Grid<B::Bit>) -> B::Bit {
+        let circuit = &self.circuits[&missing_neighbors(grid, x, y)];
+
+        // Missing neighbors are constants in their circuit, so their inputs
+        // are never read and the cell itself can stand in for them
+        let mut inputs: Vec<&B::Bit> = grid
+            .neighborhood(x, y)
+            .map(|neighbor| neighbor.unwrap_or(&grid[(x, y)]))
+            .collect();
+        inputs.push(&grid[(x, y)]);
+
+        circuit.evaluate(&self.ops, &inputs).remove(0)
+    }
+}
+
+/// Bit `i` is set if neighbor `i` of `(x, y)` lies beyond a dead border
+fn missing_neighbors<T>(grid: &Grid<T>, x: usize, y: usize) -> u8 {
+    grid.neighborhood(x, y)
+        .enumerate()
+        .filter(|(_, neighbor)| neighbor.is_none())
+        .fold(0, |mask, (i, _)| mask | 1 << i)
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// What a cell on the edge of the grid sees beyond it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Boundary {
+    /// Everything outside the grid is a dead cell
+    #[default]
+    Dead,
+    /// The grid wraps around, the last row neighbors the first one
+    Torus,
+    /// The grid is mirrored at its edges, the row above the first one is a
+    /// copy of the first row itself
+    Reflect,
+}
+
+impl Boundary {
+    /// Coordinates of the eight neighbors of `(x, y)` on an `m * n` grid,
+    /// always in the same order, with `None` for the neighbors on a dead
+    /// border
+    ///
+    /// On small grids the same cell can show up more than once, and with
+    /// `Reflect` an edge cell can be its own neighbor.
+    pub(crate) fn neighborhood(
+        self,
+        x: usize,
+        y: usize,
+        m: usize,
+        n: usize,
+    ) -> impl Iterator<Item = Option<(usize, usize)>> {
+        const DIRECTIONS: [(isize, isize); 8] = [
+            (-1, -1),
+            (-1, 0),
+            (-1, 1),
+            (0, -1),
+            (0, 1),
+            (1, -1),
+            (1, 0),
+            (1, 1),
+        ];
+
+        DIRECTIONS.into_iter().map(move |(dx, dy)| {
+            let nx = self.resolve(x as isize + dx, m)?;
+            let ny = self.resolve(y as isize + dy, n)?;
+            Some((nx, ny))
+        })
+    }
+
+    /// `i` itself followed by the indices before and after it on a line of
+    /// `len` cells, leaving out those on a dead border
+    ///
+    /// Neighbors resolve rows and columns independently, so the 3x3 block
+    /// around a cell is every row of `resolve_around(x, m)` crossed with
+    /// every column of `resolve_around(y, n)`.
+    pub(crate) fn resolve_around(self, i: usize, len: usize) -> impl Iterator<Item = usize> {
+        [0, -1, 1]
+            .into_iter()
+            .filter_map(move |d| self.resolve(i as isize + d, len))
+    }
+
+    /// Map a possibly out-of-range index back onto `0..len`, `None` if it
+    /// falls on a dead border
+    pub(crate) fn resolve(self, i: isize, len: usize) -> Option<usize> {
+        let len = len as isize;
+        if (0..len).contains(&i) {
+            return Some(i as usize);
+        }
+
+        match self {
+            Boundary::Dead => None,
+            Boundary::Torus => Some(i.rem_euclid(len) as usize),
+            Boundary::Reflect => Some(if i < 0 { -i - 1 } else { 2 * len - i - 1 } as usize),
+        }
+    }
+}
+
+impl FromStr for Boundary {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dead" => Ok(Boundary::Dead),
+            "torus" => Ok(Boundary::Torus),
+            "reflect" => Ok(Boundary::Reflect),
+            _ => Err(format!(
+                "unknown boundary `{s}`, expected `dead`, `torus` or `reflect`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Boundary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Boundary::Dead => "dead",
+            Boundary::Torus => "torus",
+            Boundary::Reflect => "reflect",
+        })
+    }
+}
@@ -0,0 +1,71 @@
+use crate::client::EncryptedGrid;
+use crate::grid::Grid;
+use crate::phases::Traffic;
+use rayon::prelude::*;
+use serde::Serialize;
+
+/// Grid storage of a server that evaluates one generation after the other
+///
+/// A generation is written into the back buffer while the current one is
+/// read by reference from the front grid, then the two are swapped. Only the
+/// first generation allocates its buffer and no generation copies a
+/// ciphertext.
+pub(crate) struct DoubleBuffer<C> {
+    front: Grid<C>,
+    /// Empty until the first generation is written into it
+    back: Vec<C>,
+}
+
+impl<C: Clone + Send + Sync + Serialize> DoubleBuffer<C> {
+    /// Take over the cells of `grid`, without copying them
+    pub(crate) fn new(grid: EncryptedGrid<C>) -> Self {
+        DoubleBuffer {
+            front: grid,
+            back: vec![],
+        }
+    }
+
+    /// Evaluate the next generation on the current rayon pool, `f` gets the
+    /// coordinates of a cell and the current generation
+    pub(crate) fn step(&mut self, f: impl Fn(usize, usize, &Grid<C>) -> C + Sync) -> Traffic {
+        let mut traffic = Traffic::default();
+        let mut back = std::mem::take(&mut self.back);
+        let (front, n) = (&self.front, self.front.dimensions().1);
+        let len = front.cells().len();
+        if back.len() == len {
+            back.par_iter_mut()
+                .enumerate()
+                .for_each(|(k, cell)| *cell = f(k / n, k % n, front));
+        } else {
+            back = (0..len)
+                .into_par_iter()
+                .map(|k| f(k / n, k % n, front))
+                .collect();
+            traffic.allocations += 1;
+        }
+
+        let back = self.front.with_cells(back);
+        self.back = std::mem::replace(&mut self.front, back).into_cells();
+        traffic
+    }
+
+    /// A copy of the current generation, to keep it once the buffers move on
+    pub(crate) fn snapshot(&self) -> (EncryptedGrid<C>, Traffic) {
+        let cells = self.front.cells();
+        let traffic = Traffic {
+            allocations: 1,
+            copied: cells.len() as u64 * cells.first().map_or(0, cell_bytes),
+        };
+        (self.front.clone(), traffic)
+    }
+
+    /// Hand the current generation over, without copying it
+    pub(crate) fn into_grid(self) -> EncryptedGrid<C> {
+        self.front
+    }
+}
+
+/// Bytes a ciphertext takes, which is the same for every cell of a grid
+pub(crate) fn cell_bytes<C: Serialize>(cell: &C) -> u64 {
+    bincode::serialized_size(cell).unwrap_or(0)
+}
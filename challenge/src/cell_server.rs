@@ -0,0 +1,96 @@
+use crate::buffer::DoubleBuffer;
+use crate::client::EncryptedGrid;
+use crate::grid::Grid;
+use crate::phases::Traffic;
+use crate::server::Evolve;
+use rayon::ThreadPool;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// How a `CellServer` computes the next state of a cell
+pub(crate) trait CellUpdate: Sync {
+    type Cell: Clone + Send + Sync + Serialize;
+
+    /// The next state of cell `(x, y)` of the current generation `grid`
+    fn update_cell(&self, x: usize, y: usize, grid: &Grid<Self::Cell>) -> Self::Cell;
+}
+
+/// Server that evaluates one generation after the other in a `DoubleBuffer`,
+/// every cell of a generation in parallel on its pool
+///
+/// The servers of the single-bit and shortint backends only differ in how
+/// they update a cell, which is `U`.
+pub(crate) struct CellServer<U: CellUpdate> {
+    update: U,
+    pool: ThreadPool,
+    /// Taken by the first run
+    grid: Mutex<Option<EncryptedGrid<U::Cell>>>,
+    generation_times: Mutex<Vec<f64>>,
+    traffic: Mutex<Vec<Traffic>>,
+}
+
+impl<U: CellUpdate> CellServer<U> {
+    /// Create a server updating cells with `update` on the workers of `pool`
+    pub(crate) fn with_update(update: U, pool: ThreadPool, grid: EncryptedGrid<U::Cell>) -> Self {
+        CellServer {
+            update,
+            pool,
+            grid: Mutex::new(Some(grid)),
+            generation_times: Mutex::default(),
+            traffic: Mutex::default(),
+        }
+    }
+
+    fn take_grid(&self) -> DoubleBuffer<U::Cell> {
+        let grid = self.grid.lock().unwrap().take();
+        DoubleBuffer::new(grid.expect("the server already ran its grid"))
+    }
+
+    fn record_generation(&self, start: Instant, traffic: Traffic) {
+        let seconds = start.elapsed().as_secs_f64();
+        self.generation_times.lock().unwrap().push(seconds);
+        self.traffic.lock().unwrap().push(traffic);
+    }
+
+    fn step(&self, buffer: &mut DoubleBuffer<U::Cell>) -> Traffic {
+        self.pool
+            .install(|| buffer.step(|i, j, grid| self.update.update_cell(i, j, grid)))
+    }
+}
+
+impl<U: CellUpdate> Evolve for CellServer<U> {
+    type Cell = U::Cell;
+
+    fn run(&self, steps: u32) -> EncryptedGrid<U::Cell> {
+        let mut buffer = self.take_grid();
+        for _ in 0..steps {
+            let start = Instant::now();
+            let traffic = self.step(&mut buffer);
+            self.record_generation(start, traffic);
+        }
+        buffer.into_grid()
+    }
+
+    fn generations(&self, steps: u32) -> Vec<EncryptedGrid<U::Cell>> {
+        let mut buffer = self.take_grid();
+        let mut generations = vec![];
+        for _ in 0..steps {
+            let start = Instant::now();
+            let (grid, copy) = buffer.snapshot();
+            generations.push(grid);
+            let traffic = self.step(&mut buffer);
+            self.record_generation(start, copy + traffic);
+        }
+        generations.push(buffer.into_grid());
+        generations
+    }
+
+    fn generation_times(&self) -> Vec<f64> {
+        self.generation_times.lock().unwrap().clone()
+    }
+
+    fn traffic(&self) -> Vec<Traffic> {
+        self.traffic.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,678 @@
+use crate::rule::Rule;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use tfhe::FheBool;
+use tfhe::core_crypto::prelude::lwe_ciphertext_opposite_assign;
+use tfhe::prelude::*;
+use tfhe::shortint::ciphertext::Degree;
+use tfhe::shortint::{Ciphertext, ServerKey as ShortintServerKey};
+
+/// Index of a gate in its circuit
+pub(crate) type Node = usize;
+
+/// A gate of a boolean circuit, operands always come earlier in the circuit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Gate {
+    Input(usize),
+    Const(bool),
+    Not(Node),
+    And(Node, Node),
+    Or(Node, Node),
+    Xor(Node, Node),
+}
+
+impl Gate {
+    fn operands(self) -> impl Iterator<Item = Node> {
+        let (a, b) = match self {
+            Gate::Input(_) | Gate::Const(_) => (None, None),
+            Gate::Not(a) => (Some(a), None),
+            Gate::And(a, b) | Gate::Or(a, b) | Gate::Xor(a, b) => (Some(a), Some(b)),
+        };
+        a.into_iter().chain(b)
+    }
+
+    fn map(self, f: impl Fn(Node) -> Node) -> Gate {
+        match self {
+            Gate::Input(_) | Gate::Const(_) => self,
+            Gate::Not(a) => Gate::Not(f(a)),
+            Gate::And(a, b) => Gate::And(f(a), f(b)),
+            Gate::Or(a, b) => Gate::Or(f(a), f(b)),
+            Gate::Xor(a, b) => Gate::Xor(f(a), f(b)),
+        }
+    }
+
+    /// Whether evaluating the gate takes a bootstrap, `Not` and constants
+    /// are free
+    fn is_bootstrapped(self) -> bool {
+        matches!(self, Gate::And(..) | Gate::Or(..) | Gate::Xor(..))
+    }
+
+    /// The same gate with the operands of a binary gate in a fixed order
+    fn canonical(self) -> Gate {
+        match self {
+            Gate::And(a, b) => Gate::And(a.min(b), a.max(b)),
+            Gate::Or(a, b) => Gate::Or(a.min(b), a.max(b)),
+            Gate::Xor(a, b) => Gate::Xor(a.min(b), a.max(b)),
+            gate => gate,
+        }
+    }
+}
+
+/// A DAG of boolean gates over encrypted bits, in topological order
+///
+/// Circuits are built gate by gate with the builder methods, which do not
+/// simplify anything, and are then cleaned up by `optimize`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Circuit {
+    gates: Vec<Gate>,
+    outputs: Vec<Node>,
+}
+
+impl Circuit {
+    pub(crate) fn input(&mut self, index: usize) -> Node {
+        self.push(Gate::Input(index))
+    }
+
+    pub(crate) fn constant(&mut self, value: bool) -> Node {
+        self.push(Gate::Const(value))
+    }
+
+    pub(crate) fn not(&mut self, a: Node) -> Node {
+        self.push(Gate::Not(a))
+    }
+
+    pub(crate) fn and(&mut self, a: Node, b: Node) -> Node {
+        self.push(Gate::And(a, b))
+    }
+
+    pub(crate) fn or(&mut self, a: Node, b: Node) -> Node {
+        self.push(Gate::Or(a, b))
+    }
+
+    pub(crate) fn xor(&mut self, a: Node, b: Node) -> Node {
+        self.push(Gate::Xor(a, b))
+    }
+
+    pub(crate) fn output(&mut self, node: Node) {
+        self.outputs.push(node);
+    }
+
+    fn push(&mut self, gate: Gate) -> Node {
+        debug_assert!(gate.operands().all(|operand| operand < self.gates.len()));
+        self.gates.push(gate);
+        self.gates.len() - 1
+    }
+
+    /// Number of bootstrapped gates, i.e. everything but inputs, constants
+    /// and negations
+    pub(crate) fn gate_count(&self) -> usize {
+        self.gates
+            .iter()
+            .filter(|gate| gate.is_bootstrapped())
+            .count()
+    }
+
+    /// Largest number of bootstrapped gates on a path from an input to an
+    /// output
+    pub(crate) fn depth(&self) -> usize {
+        self.depths()
+            .into_iter()
+            .enumerate()
+            .filter(|(node, _)| self.outputs.contains(node))
+            .map(|(_, depth)| depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn depths(&self) -> Vec<usize> {
+        let mut depths: Vec<usize> = Vec::with_capacity(self.gates.len());
+        for gate in &self.gates {
+            let operands = gate.operands().map(|operand| depths[operand]).max();
+            depths.push(operands.unwrap_or(0) + gate.is_bootstrapped() as usize);
+        }
+        depths
+    }
+
+    /// Replace inputs by constants, e.g. neighbors beyond a dead border
+    pub(crate) fn bind(&self, bindings: &[(usize, bool)]) -> Circuit {
+        self.rebuild(|circuit, gate| match gate {
+            Gate::Input(index) => match bindings.iter().find(|(bound, _)| *bound == index) {
+                Some(&(_, value)) => circuit.constant(value),
+                None => circuit.input(index),
+            },
+            gate => circuit.push(gate),
+        })
+    }
+
+    /// Run every pass until the circuit stops shrinking
+    pub(crate) fn optimize(&self) -> Circuit {
+        let mut circuit = self.clone();
+        loop {
+            let size = (circuit.gates.len(), circuit.depth());
+            circuit = circuit
+                .fold_constants()
+                .eliminate_common_subexpressions()
+                .balance()
+                .remove_dead_gates();
+            if (circuit.gates.len(), circuit.depth()) >= size {
+                return circuit;
+            }
+        }
+    }
+
+    /// Fold constants and other operands that decide a gate on their own,
+    /// e.g. `a & false`, `a | a` or `a ^ !a`
+    pub(crate) fn fold_constants(&self) -> Circuit {
+        self.rebuild(|circuit, gate| circuit.simplify(gate))
+    }
+
+    fn simplify(&mut self, gate: Gate) -> Node {
+        let constant = |circuit: &Circuit, node: Node| match circuit.gates[node] {
+            Gate::Const(value) => Some(value),
+            _ => None,
+        };
+        let complement = |circuit: &Circuit, a: Node, b: Node| {
+            circuit.gates[a] == Gate::Not(b) || circuit.gates[b] == Gate::Not(a)
+        };
+        // Whether `b` is `a op _` or `_ op a`, for absorption
+        let has_operand = |circuit: &Circuit, b: Node, a: Node, or: bool| match circuit.gates[b] {
+            Gate::Or(x, y) if or => x == a || y == a,
+            Gate::And(x, y) if !or => x == a || y == a,
+            _ => false,
+        };
+
+        match gate {
+            Gate::Not(a) => match self.gates[a] {
+                Gate::Const(value) => self.constant(!value),
+                Gate::Not(inner) => inner,
+                _ => self.push(gate),
+            },
+            Gate::And(a, b) | Gate::Or(a, b) | Gate::Xor(a, b) => {
+                let (a, b) = match constant(self, a) {
+                    Some(_) => (b, a),
+                    None => (a, b),
+                };
+                match (gate, constant(self, b)) {
+                    (Gate::And(..), Some(false)) | (Gate::Or(..), Some(true)) => b,
+                    (Gate::Or(..) | Gate::Xor(..), Some(false)) | (Gate::And(..), Some(true)) => a,
+                    (Gate::Xor(..), Some(true)) => self.simplify(Gate::Not(a)),
+                    _ if a == b => match gate {
+                        Gate::Xor(..) => self.constant(false),
+                        _ => a,
+                    },
+                    _ if complement(self, a, b) => match gate {
+                        Gate::And(..) => self.constant(false),
+                        _ => self.constant(true),
+                    },
+                    (Gate::And(..), _) if has_operand(self, b, a, true) => a,
+                    (Gate::And(..), _) if has_operand(self, a, b, true) => b,
+                    (Gate::Or(..), _) if has_operand(self, b, a, false) => a,
+                    (Gate::Or(..), _) if has_operand(self, a, b, false) => b,
+                    // Negations move out of a xor, so that `!a ^ b` and
+                    // `a ^ !b` end up as the same gate
+                    (Gate::Xor(..), _) => match (self.gates[a], self.gates[b]) {
+                        (Gate::Not(a), Gate::Not(b)) => self.push(Gate::Xor(a, b)),
+                        (Gate::Not(a), _) => {
+                            let xor = self.push(Gate::Xor(a, b));
+                            self.push(Gate::Not(xor))
+                        }
+                        (_, Gate::Not(b)) => {
+                            let xor = self.push(Gate::Xor(a, b));
+                            self.push(Gate::Not(xor))
+                        }
+                        _ => self.push(Gate::Xor(a, b)),
+                    },
+                    _ => self.push(gate),
+                }
+            }
+            gate => self.push(gate),
+        }
+    }
+
+    /// Evaluate every distinct gate once, binary gates are compared
+    /// regardless of the order of their operands
+    pub(crate) fn eliminate_common_subexpressions(&self) -> Circuit {
+        let mut seen = HashMap::new();
+        self.rebuild(|circuit, gate| {
+            *seen
+                .entry(gate.canonical())
+                .or_insert_with(|| circuit.push(gate.canonical()))
+        })
+    }
+
+    /// Rebuild chains of the same associative gate as balanced trees
+    ///
+    /// Only gates used once are merged into a chain, so the gate count never
+    /// grows. The shallowest operands are combined first, which gives the
+    /// smallest depth for the chain.
+    pub(crate) fn balance(&self) -> Circuit {
+        let uses = self.uses();
+        let mut map: Vec<Node> = Vec::with_capacity(self.gates.len());
+        let mut circuit = Circuit::default();
+        let mut new_depths: Vec<usize> = vec![];
+
+        for (node, &gate) in self.gates.iter().enumerate() {
+            let combine: Option<fn(Node, Node) -> Gate> = match gate {
+                Gate::And(..) => Some(Gate::And),
+                Gate::Or(..) => Some(Gate::Or),
+                Gate::Xor(..) => Some(Gate::Xor),
+                _ => None,
+            };
+            let new = match combine {
+                // Every gate is rebuilt as the root of its chain, the inner
+                // gates of a chain are then left unused and dropped
+                Some(combine) => {
+                    let mut leaves = vec![];
+                    self.chain_leaves(node, gate, &uses, &mut leaves);
+                    let mut leaves: Vec<(usize, Node)> = leaves
+                        .into_iter()
+                        .map(|leaf| (new_depths[map[leaf]], map[leaf]))
+                        .collect();
+                    while leaves.len() > 1 {
+                        leaves.sort_by_key(|&(depth, _)| std::cmp::Reverse(depth));
+                        let (da, a) = leaves.pop().unwrap();
+                        let (db, b) = leaves.pop().unwrap();
+                        let combined = circuit.push(combine(a, b));
+                        new_depths.push(da.max(db) + 1);
+                        leaves.push((da.max(db) + 1, combined));
+                    }
+                    leaves[0].1
+                }
+                None => {
+                    let new = circuit.push(gate.map(|operand| map[operand]));
+                    let depth = gate
+                        .operands()
+                        .map(|operand| new_depths[map[operand]])
+                        .max();
+                    new_depths.push(depth.unwrap_or(0));
+                    new
+                }
+            };
+            map.push(new);
+        }
+
+        circuit.outputs = self.outputs.iter().map(|&output| map[output]).collect();
+        circuit.remove_dead_gates()
+    }
+
+    /// Operands of the chain of `gate`s rooted at `node`
+    fn chain_leaves(&self, node: Node, root: Gate, uses: &[usize], leaves: &mut Vec<Node>) {
+        for operand in self.gates[node].operands() {
+            let same =
+                std::mem::discriminant(&self.gates[operand]) == std::mem::discriminant(&root);
+            if same && uses[operand] == 1 && !self.outputs.contains(&operand) {
+                self.chain_leaves(operand, root, uses, leaves);
+            } else {
+                leaves.push(operand);
+            }
+        }
+    }
+
+    /// Drop gates no output depends on
+    pub(crate) fn remove_dead_gates(&self) -> Circuit {
+        let mut live = vec![false; self.gates.len()];
+        for &output in &self.outputs {
+            live[output] = true;
+        }
+        for node in (0..self.gates.len()).rev() {
+            if live[node] {
+                for operand in self.gates[node].operands() {
+                    live[operand] = true;
+                }
+            }
+        }
+
+        let mut map = vec![0; self.gates.len()];
+        let mut circuit = Circuit::default();
+        for (node, gate) in self.gates.iter().enumerate() {
+            if live[node] {
+                map[node] = circuit.push(gate.map(|operand| map[operand]));
+            }
+        }
+        circuit.outputs = self.outputs.iter().map(|&output| map[output]).collect();
+        circuit
+    }
+
+    fn uses(&self) -> Vec<usize> {
+        let mut uses = vec![0; self.gates.len()];
+        for gate in &self.gates {
+            for operand in gate.operands() {
+                uses[operand] += 1;
+            }
+        }
+        for &output in &self.outputs {
+            uses[output] += 1;
+        }
+        uses
+    }
+
+    /// Build a new circuit gate by gate, `f` gets every gate with its
+    /// operands already mapped into the new circuit
+    fn rebuild(&self, mut f: impl FnMut(&mut Circuit, Gate) -> Node) -> Circuit {
+        let mut map: Vec<Node> = Vec::with_capacity(self.gates.len());
+        let mut circuit = Circuit::default();
+        for gate in &self.gates {
+            let node = f(&mut circuit, gate.map(|operand| map[operand]));
+            map.push(node);
+        }
+        circuit.outputs = self.outputs.iter().map(|&output| map[output]).collect();
+        circuit
+    }
+
+    /// Evaluate the circuit, `inputs[i]` is the bit of `Gate::Input(i)`
+    ///
+    /// Inputs are only borrowed, a bit is cloned only if an input is also
+    /// an output or an output is listed twice.
+    pub(crate) fn evaluate<B: BitOps>(&self, ops: &B, inputs: &[&B::Bit]) -> Vec<B::Bit> {
+        let mut values: Vec<Option<Cow<B::Bit>>> = Vec::with_capacity(self.gates.len());
+        for gate in &self.gates {
+            let get = |node: Node| values[node].as_deref().unwrap();
+            let value = match *gate {
+                Gate::Input(index) => Cow::Borrowed(inputs[index]),
+                Gate::Const(bit) => Cow::Owned(ops.constant(bit)),
+                Gate::Not(a) => Cow::Owned(ops.not(get(a))),
+                Gate::And(a, b) => Cow::Owned(ops.and(get(a), get(b))),
+                Gate::Or(a, b) => Cow::Owned(ops.or(get(a), get(b))),
+                Gate::Xor(a, b) => Cow::Owned(ops.xor(get(a), get(b))),
+            };
+            values.push(Some(value));
+        }
+
+        let mut outputs: Vec<B::Bit> = Vec::with_capacity(self.outputs.len());
+        for (k, &output) in self.outputs.iter().enumerate() {
+            let bit = match self.outputs[..k].iter().position(|&o| o == output) {
+                Some(earlier) => outputs[earlier].clone(),
+                None => values[output].take().unwrap().into_owned(),
+            };
+            outputs.push(bit);
+        }
+        outputs
+    }
+}
+
+/// Bits a circuit can be evaluated on
+pub(crate) trait BitOps: Sync {
+    type Bit: Clone + Send + Sync + Serialize;
+
+    fn constant(&self, value: bool) -> Self::Bit;
+    fn not(&self, a: &Self::Bit) -> Self::Bit;
+    fn and(&self, a: &Self::Bit, b: &Self::Bit) -> Self::Bit;
+    fn or(&self, a: &Self::Bit, b: &Self::Bit) -> Self::Bit;
+    fn xor(&self, a: &Self::Bit, b: &Self::Bit) -> Self::Bit;
+}
+
+/// `FheBool`s, the server key has to be installed on the evaluating thread
+pub(crate) struct FheBoolOps;
+
+impl BitOps for FheBoolOps {
+    type Bit = FheBool;
+
+    fn constant(&self, value: bool) -> FheBool {
+        FheBool::encrypt_trivial(value)
+    }
+
+    fn not(&self, a: &FheBool) -> FheBool {
+        !a
+    }
+
+    fn and(&self, a: &FheBool, b: &FheBool) -> FheBool {
+        a & b
+    }
+
+    fn or(&self, a: &FheBool, b: &FheBool) -> FheBool {
+        a | b
+    }
+
+    fn xor(&self, a: &FheBool, b: &FheBool) -> FheBool {
+        a ^ b
+    }
+}
+
+/// Shortint ciphertexts holding 0 or 1, every binary gate is a bivariate
+/// bootstrap
+impl BitOps for ShortintServerKey {
+    type Bit = Ciphertext;
+
+    fn constant(&self, value: bool) -> Ciphertext {
+        self.create_trivial(value as u64)
+    }
+
+    /// `1 - a`, leveled like `FheBool`'s not: the ciphertext is negated and
+    /// shifted back by one, which keeps a bit a bit
+    fn not(&self, a: &Ciphertext) -> Ciphertext {
+        let mut result = a.clone();
+        lwe_ciphertext_opposite_assign(&mut result.ct);
+        self.unchecked_scalar_add_assign(&mut result, 1);
+        result.degree = Degree::new(1);
+        result
+    }
+
+    fn and(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        self.bitand(a, b)
+    }
+
+    fn or(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        self.bitor(a, b)
+    }
+
+    fn xor(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        self.bitxor(a, b)
+    }
+}
+
+/// Cleartext bits, the reference a circuit is checked against
+pub(crate) struct Plain;
+
+impl BitOps for Plain {
+    type Bit = bool;
+
+    fn constant(&self, value: bool) -> bool {
+        value
+    }
+
+    fn not(&self, a: &bool) -> bool {
+        !a
+    }
+
+    fn and(&self, a: &bool, b: &bool) -> bool {
+        a & b
+    }
+
+    fn or(&self, a: &bool, b: &bool) -> bool {
+        a | b
+    }
+
+    fn xor(&self, a: &bool, b: &bool) -> bool {
+        a ^ b
+    }
+}
+
+/// Check the cell update circuit of `rule` on all 512 neighborhoods
+/// with the plaintext evaluator
+pub(crate) fn check_life(circuit: &Circuit, rule: Rule) -> Result<(), String> {
+    for neighborhood in 0..512usize {
+        let inputs: Vec<bool> = (0..9).map(|i| (neighborhood >> i) & 1 == 1).collect();
+        let count = inputs[..8].iter().filter(|&&alive| alive).count();
+        let expected = rule.next(inputs[8] as u8, count as u8) == 1;
+        let inputs: Vec<&bool> = inputs.iter().collect();
+        if circuit.evaluate(&Plain, &inputs) != [expected] {
+            return Err(format!(
+                "compiled circuit disagrees with {rule} on neighborhood {neighborhood:#011b}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The cell update as a circuit
+///
+/// Inputs 0 to 7 are the neighbors and input 8 is the cell itself. The
+/// neighbor count is summed bit-sliced with a Wallace tree of half and full
+/// adders, and the rule is a sum of products over the bits of the count.
+pub(crate) fn life(rule: Rule) -> Circuit {
+    let mut circuit = Circuit::default();
+    let neighbors = (0..8).map(|i| circuit.input(i)).collect();
+    let cell = circuit.input(8);
+
+    let bits = count_bits(&mut circuit, neighbors);
+    let next = apply_rule(&mut circuit, rule, &bits, cell);
+    circuit.output(next);
+    circuit.optimize()
+}
+
+/// Sum single-bit inputs into a little-endian binary number
+///
+/// Bits of the same weight are reduced column by column with full adders
+/// (three bits in, sum and carry out) and half adders (two bits in) until
+/// every column holds a single bit, i.e. a Wallace tree.
+fn count_bits(circuit: &mut Circuit, inputs: Vec<Node>) -> Vec<Node> {
+    let mut columns = vec![inputs];
+    let mut weight = 0;
+    while weight < columns.len() {
+        while columns[weight].len() > 1 {
+            let mut sums = vec![];
+            let mut carries = vec![];
+            let mut bits = std::mem::take(&mut columns[weight]).into_iter();
+            while let Some(a) = bits.next() {
+                match (bits.next(), bits.next()) {
+                    (Some(b), Some(c)) => {
+                        // Full adder
+                        let a_xor_b = circuit.xor(a, b);
+                        sums.push(circuit.xor(a_xor_b, c));
+                        let (a_and_b, c_and) = (circuit.and(a, b), circuit.and(c, a_xor_b));
+                        carries.push(circuit.or(a_and_b, c_and));
+                    }
+                    (Some(b), None) => {
+                        // Half adder
+                        sums.push(circuit.xor(a, b));
+                        carries.push(circuit.and(a, b));
+                    }
+                    _ => sums.push(a),
+                }
+            }
+
+            columns[weight] = sums;
+            if !carries.is_empty() {
+                if columns.len() == weight + 1 {
+                    columns.push(vec![]);
+                }
+                columns[weight + 1].extend(carries);
+            }
+        }
+        weight += 1;
+    }
+
+    columns
+        .into_iter()
+        .map_while(|mut column| column.pop())
+        .collect()
+}
+
+/// Evaluate the rule as a sum of products over the bits of the count
+///
+/// `count == k` is the AND of a low half (bits 0 and 1) and a high half
+/// (bits 2 and 3), and minterms sharing a half share its gates. A count
+/// that is in both the birth and survival sets ignores the cell's state.
+fn apply_rule(circuit: &mut Circuit, rule: Rule, bits: &[Node], cell: Node) -> Node {
+    let (low, high) = bits.split_at(bits.len().min(2));
+    let mut lows = HashMap::new();
+    let mut highs = HashMap::new();
+    let dead = circuit.not(cell);
+
+    let mut next: Option<Node> = None;
+    for k in 0..(1usize << bits.len()).min(9) {
+        let state = match (rule.born(k), rule.survives(k)) {
+            (false, false) => continue,
+            (true, true) => None,
+            (true, false) => Some(dead),
+            (false, true) => Some(cell),
+        };
+        let low = *lows
+            .entry(k & 0b11)
+            .or_insert_with(|| minterm(circuit, low, k & 0b11));
+        let high = *highs
+            .entry(k >> 2)
+            .or_insert_with(|| minterm(circuit, high, k >> 2));
+
+        let term = [low, high, state]
+            .into_iter()
+            .flatten()
+            .reduce(|acc, bit| circuit.and(acc, bit))
+            .unwrap_or_else(|| circuit.constant(true));
+        next = Some(match next {
+            Some(acc) => circuit.or(acc, term),
+            None => term,
+        });
+    }
+
+    next.unwrap_or_else(|| circuit.constant(false))
+}
+
+/// `bits == value` as an AND of literals, `None` if there are no bits to test
+fn minterm(circuit: &mut Circuit, bits: &[Node], value: usize) -> Option<Node> {
+    let literals: Vec<Node> = bits
+        .iter()
+        .enumerate()
+        .map(|(i, &bit)| match (value >> i) & 1 {
+            1 => bit,
+            _ => circuit.not(bit),
+        })
+        .collect();
+    literals
+        .into_iter()
+        .reduce(|acc, literal| circuit.and(acc, literal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn life_follows_the_rule() {
+        for rule in ["B3/S23", "B36/S23", "B/S", "B012345678/S012345678", "B0/S8"] {
+            let rule = rule.parse().unwrap();
+            check_life(&life(rule), rule).unwrap();
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let mut counts = || -> String {
+                (0..9)
+                    .filter(|_| rng.random_bool(0.5))
+                    .map(|count| char::from(b'0' + count))
+                    .collect()
+            };
+            let (birth, survival) = (counts(), counts());
+            let rule = format!("B{birth}/S{survival}").parse().unwrap();
+            check_life(&life(rule), rule).unwrap();
+        }
+    }
+
+    #[test]
+    fn shortint_not_feeds_bootstrapped_gates() {
+        let client_key =
+            tfhe::shortint::ClientKey::new(crate::shortint_server::SHORTINT_PARAMETERS);
+        let server_key = ShortintServerKey::new(&client_key);
+        let encrypt = |bit: bool| client_key.encrypt(bit as u64);
+
+        for a in [false, true] {
+            let not_a = server_key.not(&encrypt(a));
+            assert_eq!(client_key.decrypt(&not_a), !a as u64, "not {a}");
+            let twice = server_key.not(&not_a);
+            assert_eq!(client_key.decrypt(&twice), a as u64, "not not {a}");
+            for b in [false, true] {
+                let gates = [
+                    (server_key.and(&not_a, &encrypt(b)), !a & b),
+                    (server_key.or(&not_a, &encrypt(b)), !a | b),
+                    (server_key.xor(&not_a, &encrypt(b)), !a ^ b),
+                ];
+                for (bit, expected) in gates {
+                    assert_eq!(client_key.decrypt(&bit), expected as u64, "{a} {b}");
+                }
+            }
+        }
+    }
+}
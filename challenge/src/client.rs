@@ -1,52 +1,648 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Instant;
 use tfhe::prelude::*;
-use tfhe::{ClientKey, ConfigBuilder, FheUint8, ServerKey};
+use tfhe::shortint::ciphertext::Degree;
+use tfhe::shortint::parameters::{
+    COMP_PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M128,
+    PARAM_KEYSWITCH_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M128,
+    PARAM_PKE_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M128,
+};
+use tfhe::shortint::{
+    Ciphertext, ClientKey as ShortintClientKey, CompressedCiphertext,
+    CompressedServerKey as CompressedShortintServerKey, ServerKey as ShortintServerKey,
+};
+use tfhe::{
+    ClientKey, CompactPublicKey, CompressedCiphertextList, CompressedCiphertextListBuilder,
+    CompressedServerKey, Config, FheBool, FheUint8, HlCompressible, HlExpandable, ServerKey,
+};
 
-pub(crate) type EncryptedGrid = Vec<Vec<FheUint8>>;
+use crate::backend::Backend;
+use crate::bitboard::Bitboard;
+use crate::boundary::Boundary;
+use crate::format::{self, Header, Kind};
+use crate::grid::Grid;
+use crate::hashlife::Hashlife;
+use crate::keystore::{KeyStore, Keys};
+use crate::params::ParameterSet;
+use crate::phases::{Idle, Phase, Phases, Traffic};
+use crate::public_key::{self, Contribution, PublicCell};
+use crate::rule::Rule;
+use crate::verification::Verification;
+
+pub(crate) type EncryptedGrid<C = FheUint8> = Grid<C>;
+
+/// A ciphertext type a single cell can be encrypted into
+pub(crate) trait EncryptedCell: Sized {
+    type ClientKey;
+    type ServerKey: Clone;
+
+    /// The server key among `keys`, if they are for this kind of cell
+    fn server_key(keys: &Keys) -> Option<&Self::ServerKey>;
+    fn encrypt_cell(cell: u8, client_key: &Self::ClientKey) -> Self;
+    fn decrypt_cell(&self, client_key: &Self::ClientKey) -> u8;
+}
+
+impl EncryptedCell for FheUint8 {
+    type ClientKey = ClientKey;
+    type ServerKey = ServerKey;
+
+    fn server_key(keys: &Keys) -> Option<&ServerKey> {
+        integer_server_key(keys)
+    }
+
+    fn encrypt_cell(cell: u8, client_key: &ClientKey) -> Self {
+        FheUint8::encrypt(cell, client_key)
+    }
+
+    fn decrypt_cell(&self, client_key: &ClientKey) -> u8 {
+        FheUint8::decrypt(self, client_key)
+    }
+}
+
+impl EncryptedCell for FheBool {
+    type ClientKey = ClientKey;
+    type ServerKey = ServerKey;
+
+    fn server_key(keys: &Keys) -> Option<&ServerKey> {
+        integer_server_key(keys)
+    }
+
+    fn encrypt_cell(cell: u8, client_key: &ClientKey) -> Self {
+        FheBool::encrypt(cell == 1, client_key)
+    }
+
+    fn decrypt_cell(&self, client_key: &ClientKey) -> u8 {
+        FheBool::decrypt(self, client_key) as u8
+    }
+}
+
+impl EncryptedCell for Ciphertext {
+    type ClientKey = ShortintClientKey;
+    type ServerKey = ShortintServerKey;
+
+    fn server_key(keys: &Keys) -> Option<&ShortintServerKey> {
+        match keys {
+            Keys::Shortint { server_key, .. } => Some(server_key),
+            Keys::Integer { .. } => None,
+        }
+    }
+
+    /// Cells are 0 or 1, `checked_grid` makes sure of it, so their degree
+    /// is 1 instead of the whole message space
+    fn encrypt_cell(cell: u8, client_key: &ShortintClientKey) -> Self {
+        let mut encrypted = client_key.encrypt(cell as u64);
+        encrypted.degree = Degree::new(1);
+        encrypted
+    }
+
+    fn decrypt_cell(&self, client_key: &ShortintClientKey) -> u8 {
+        client_key.decrypt(self) as u8
+    }
+}
+
+fn integer_server_key(keys: &Keys) -> Option<&ServerKey> {
+    match keys {
+        Keys::Integer { server_key, .. } => Some(server_key),
+        Keys::Shortint { .. } => None,
+    }
+}
+
+/// A cell type whose server key and grid can also be sent compressed
+///
+/// Compressed artifacts are much smaller on the wire, the server decompresses
+/// them before evaluating.
+pub(crate) trait CompressedCell: EncryptedCell {
+    type CompressedServerKey: Clone;
+    type CompressedGrid;
+
+    /// The compressed server key among `keys`, if they are for this kind of
+    /// cell and were generated with `KeyConfig::Compression`
+    fn compressed_server_key(keys: &Keys) -> Option<&Self::CompressedServerKey>;
+    fn decompress_server_key(key: &Self::CompressedServerKey) -> Self::ServerKey;
+
+    /// Encrypt a plaintext grid straight into its compressed form
+    ///
+    /// `server_key` is the decompressed form of the compressed server key.
+    fn compress_grid(
+        grid: &Grid<u8>,
+        client_key: &Self::ClientKey,
+        server_key: &Self::ServerKey,
+    ) -> Result<Self::CompressedGrid, Box<dyn Error>>;
+
+    /// Expand a compressed grid back into its ciphertexts, in row-major
+    /// order
+    ///
+    /// The compressed forms only hold the cells, their shape and boundary
+    /// are in the header of the grid file.
+    fn decompress_grid(
+        grid: &Self::CompressedGrid,
+        server_key: &Self::ServerKey,
+    ) -> Result<Vec<Self>, Box<dyn Error>>;
+}
+
+impl CompressedCell for FheUint8 {
+    type CompressedServerKey = CompressedServerKey;
+    type CompressedGrid = CompressedCiphertextList;
+
+    fn compressed_server_key(keys: &Keys) -> Option<&CompressedServerKey> {
+        integer_compressed_server_key(keys)
+    }
+
+    fn decompress_server_key(key: &CompressedServerKey) -> ServerKey {
+        key.decompress()
+    }
+
+    fn compress_grid(
+        grid: &Grid<u8>,
+        client_key: &ClientKey,
+        server_key: &ServerKey,
+    ) -> Result<CompressedCiphertextList, Box<dyn Error>> {
+        compress_list::<Self>(grid, client_key, server_key)
+    }
+
+    fn decompress_grid(
+        grid: &CompressedCiphertextList,
+        server_key: &ServerKey,
+    ) -> Result<Vec<Self>, Box<dyn Error>> {
+        expand_list(grid, server_key)
+    }
+}
+
+impl CompressedCell for FheBool {
+    type CompressedServerKey = CompressedServerKey;
+    type CompressedGrid = CompressedCiphertextList;
+
+    fn compressed_server_key(keys: &Keys) -> Option<&CompressedServerKey> {
+        integer_compressed_server_key(keys)
+    }
+
+    fn decompress_server_key(key: &CompressedServerKey) -> ServerKey {
+        key.decompress()
+    }
+
+    fn compress_grid(
+        grid: &Grid<u8>,
+        client_key: &ClientKey,
+        server_key: &ServerKey,
+    ) -> Result<CompressedCiphertextList, Box<dyn Error>> {
+        compress_list::<Self>(grid, client_key, server_key)
+    }
+
+    fn decompress_grid(
+        grid: &CompressedCiphertextList,
+        server_key: &ServerKey,
+    ) -> Result<Vec<Self>, Box<dyn Error>> {
+        expand_list(grid, server_key)
+    }
+}
+
+/// Shortint has no ciphertext list compression for our parameters, every
+/// cell is a seeded ciphertext instead, i.e. only its body and the seed of
+/// its mask are sent.
+impl CompressedCell for Ciphertext {
+    type CompressedServerKey = CompressedShortintServerKey;
+    type CompressedGrid = Vec<CompressedCiphertext>;
+
+    fn compressed_server_key(keys: &Keys) -> Option<&CompressedShortintServerKey> {
+        match keys {
+            Keys::Shortint {
+                compressed_server_key,
+                ..
+            } => compressed_server_key.as_ref(),
+            Keys::Integer { .. } => None,
+        }
+    }
+
+    fn decompress_server_key(key: &CompressedShortintServerKey) -> ShortintServerKey {
+        key.decompress()
+    }
+
+    fn compress_grid(
+        grid: &Grid<u8>,
+        client_key: &ShortintClientKey,
+        _: &ShortintServerKey,
+    ) -> Result<Vec<CompressedCiphertext>, Box<dyn Error>> {
+        Ok(grid
+            .cells()
+            .iter()
+            .map(|&cell| {
+                // Of degree 1, as `encrypt_cell` makes them
+                let mut encrypted = client_key.encrypt_compressed(cell as u64);
+                encrypted.degree = Degree::new(1);
+                encrypted
+            })
+            .collect())
+    }
+
+    fn decompress_grid(
+        grid: &Vec<CompressedCiphertext>,
+        _: &ShortintServerKey,
+    ) -> Result<Vec<Self>, Box<dyn Error>> {
+        Ok(grid.iter().map(CompressedCiphertext::decompress).collect())
+    }
+}
+
+fn integer_compressed_server_key(keys: &Keys) -> Option<&CompressedServerKey> {
+    match keys {
+        Keys::Integer {
+            compressed_server_key,
+            ..
+        } => compressed_server_key.as_ref(),
+        Keys::Shortint { .. } => None,
+    }
+}
+
+/// Pack the cells of `grid`, in row-major order, into a single compressed
+/// list
+///
+/// Building the list needs the compression key, which is part of the server
+/// key of a client created with `KeyConfig::Compression`.
+fn compress_list<C>(
+    grid: &Grid<u8>,
+    client_key: &ClientKey,
+    server_key: &ServerKey,
+) -> Result<CompressedCiphertextList, Box<dyn Error>>
+where
+    C: EncryptedCell<ClientKey = ClientKey> + HlCompressible,
+{
+    let cells = grid
+        .cells()
+        .iter()
+        .map(|&cell| C::encrypt_cell(cell, client_key));
+    let list = tfhe::with_server_key_as_context(server_key.clone(), || {
+        CompressedCiphertextListBuilder::new().extend(cells).build()
+    })?;
+    Ok(list)
+}
+
+fn expand_list<C: HlExpandable + Tagged>(
+    list: &CompressedCiphertextList,
+    server_key: &ServerKey,
+) -> Result<Vec<C>, Box<dyn Error>> {
+    tfhe::with_server_key_as_context(server_key.clone(), || {
+        (0..list.len())
+            .map(|k| {
+                list.get(k)?
+                    .ok_or_else(|| format!("compressed grid lacks cell {k}").into())
+            })
+            .collect()
+    })
+}
+
+/// A random m * n grid, the same seed always gives the same grid
+pub(crate) fn random_grid(m: u32, n: u32, seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..m)
+        .map(|_| (0..n).map(|_| rng.random_range(0..=1)).collect::<Vec<u8>>())
+        .collect::<Vec<Vec<u8>>>()
+}
+
+/// What a client's keys can do besides encrypting and evaluating cells,
+/// every choice has keys of its own
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum KeyConfig {
+    #[default]
+    Plain,
+    /// Compress the integer and boolean server key and grid
+    Compression,
+    /// Make a compact public key that third parties encrypt cells with
+    PublicKey,
+}
+
+impl KeyConfig {
+    /// A config for keys of `parameters`, compression and the public key
+    /// need their own parameters made for the default set
+    pub(crate) fn config(self, parameters: ParameterSet) -> Result<Config, Box<dyn Error>> {
+        if self != KeyConfig::Plain && parameters != ParameterSet::Default {
+            return Err(
+                format!("{self:?} keys need the default parameters, not {parameters}").into(),
+            );
+        }
+        let config = parameters.config()?;
+        Ok(match self {
+            KeyConfig::Plain => config,
+            KeyConfig::Compression => {
+                config.enable_compression(COMP_PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M128)
+            }
+            // The public key encrypts with parameters of its own, the server
+            // key then holds a key switching key taking those ciphertexts to
+            // the parameters it computes with
+            KeyConfig::PublicKey => config.use_dedicated_compact_public_key_parameters((
+                PARAM_PKE_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M128,
+                PARAM_KEYSWITCH_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M128,
+            )),
+        }
+        .build())
+    }
+}
+
+/// The grid of `rows`, which have to be a non-empty rectangle of 0 and 1
+/// cells
+fn checked_grid(rows: Vec<Vec<u8>>, boundary: Boundary) -> Grid<u8> {
+    let grid = Grid::from_rows(rows, boundary);
+    assert!(
+        grid.cells().iter().all(|&cell| cell <= 1),
+        "cells have to be 0 or 1"
+    );
+    grid
+}
 
 pub(crate) struct Client {
-    client_key: ClientKey,
-    server_key: ServerKey,
-    grid: Vec<Vec<u8>>,
+    keys: ClientKeys,
+    rule: Rule,
+    grid: Grid<u8>,
+    reference: Reference,
+    phases: RefCell<Phases>,
+}
+
+/// The keys of the backend a client was created for
+enum ClientKeys {
+    /// Shared with the key store, along with the server key the grid is
+    /// encrypted for
+    Stored(Rc<Keys>),
+    /// Loaded back from a client file, which only keeps the client key
+    Saved(SavedKey),
+}
+
+#[derive(Serialize, Deserialize)]
+enum SavedKey {
+    Integer(Box<ClientKey>),
+    Shortint(Box<ShortintClientKey>),
+}
+
+/// The part of a client that has to be kept between encrypting and verifying
+#[derive(Serialize, Deserialize)]
+struct SavedClient {
+    client_key: SavedKey,
+    grid: Grid<u8>,
+}
+
+const OTHER_BACKEND: &str = "the client's keys are for another backend";
+
+impl AsRef<ClientKey> for Client {
+    fn as_ref(&self) -> &ClientKey {
+        match &self.keys {
+            ClientKeys::Stored(keys) => match keys.as_ref() {
+                Keys::Integer { client_key, .. } => Some(client_key),
+                Keys::Shortint { .. } => None,
+            },
+            ClientKeys::Saved(SavedKey::Integer(client_key)) => Some(&**client_key),
+            ClientKeys::Saved(SavedKey::Shortint(_)) => None,
+        }
+        .expect(OTHER_BACKEND)
+    }
+}
+
+impl AsRef<ShortintClientKey> for Client {
+    fn as_ref(&self) -> &ShortintClientKey {
+        match &self.keys {
+            ClientKeys::Stored(keys) => match keys.as_ref() {
+                Keys::Shortint { client_key, .. } => Some(client_key),
+                Keys::Integer { .. } => None,
+            },
+            ClientKeys::Saved(SavedKey::Shortint(client_key)) => Some(&**client_key),
+            ClientKeys::Saved(SavedKey::Integer(_)) => None,
+        }
+        .expect(OTHER_BACKEND)
+    }
 }
 
 impl Client {
-    // Create a new client with a grid of size m * n
-    pub(crate) fn new(m: u32, n: u32) -> Self {
-        let config = ConfigBuilder::default().build();
-        let client_key = ClientKey::generate(config);
-        let server_key = ServerKey::new(&client_key);
-
-        // Initial state
-        let mut rng = rand::rng();
-        let grid = (0..m)
-            .map(|_| (0..n).map(|_| rng.random_range(0..=1)).collect::<Vec<u8>>())
-            .collect::<Vec<Vec<u8>>>();
+    /// Create a client for a fixed grid, which has to be a non-empty
+    /// rectangle of 0 and 1 cells, with the keys `store` holds for `backend`
+    /// and `keys` on `parameters`, which are only generated if it holds
+    /// none yet
+    pub(crate) fn from_store(
+        grid: Vec<Vec<u8>>,
+        rule: Rule,
+        boundary: Boundary,
+        backend: Backend,
+        keys: KeyConfig,
+        parameters: ParameterSet,
+        store: &KeyStore,
+    ) -> Result<Self, Box<dyn Error>> {
+        let start = Instant::now();
+        let keys = store.keys(backend, keys, parameters)?;
+        let client = Client::with_keys(grid, rule, boundary, keys);
+        client
+            .phases
+            .borrow_mut()
+            .add(Phase::Keygen, start.elapsed().as_secs_f64());
+        Ok(client)
+    }
 
+    /// Create a client for a fixed grid, which has to be a non-empty
+    /// rectangle of 0 and 1 cells, with `keys` generated already
+    pub(crate) fn with_keys(
+        grid: Vec<Vec<u8>>,
+        rule: Rule,
+        boundary: Boundary,
+        keys: Rc<Keys>,
+    ) -> Self {
         Client {
+            keys: ClientKeys::Stored(keys),
+            rule,
+            grid: checked_grid(grid, boundary),
+            reference: Reference::default(),
+            phases: RefCell::default(),
+        }
+    }
+
+    /// Save the keys and the initial grid, so that the client can verify
+    /// a result computed by a server in another process
+    ///
+    /// # Arguments
+    /// * `path` - Where to write the client file.
+    /// * `backend` - The backend the grid is encrypted for.
+    pub(crate) fn save(&self, path: &Path, backend: Backend) -> Result<(), Box<dyn Error>> {
+        let client_key = match &self.keys {
+            ClientKeys::Stored(keys) => match keys.as_ref() {
+                Keys::Integer { client_key, .. } => SavedKey::Integer(Box::new(client_key.clone())),
+                Keys::Shortint { client_key, .. } => {
+                    SavedKey::Shortint(Box::new(client_key.clone()))
+                }
+            },
+            ClientKeys::Saved(_) => return Err("the client was already saved".into()),
+        };
+        let saved = SavedClient {
             client_key,
-            server_key,
-            grid,
+            grid: self.grid.clone(),
+        };
+        format::save(path, &self.header(Kind::Client, backend, 0), &saved)
+    }
+
+    /// Load a client written by `save`
+    ///
+    /// # Returns
+    /// The header of the client file and the client.
+    pub(crate) fn load(path: &Path) -> Result<(Header, Self), Box<dyn Error>> {
+        let (header, saved): (Header, SavedClient) = format::load(path, Kind::Client)?;
+        let client = Client {
+            keys: ClientKeys::Saved(saved.client_key),
+            rule: header.rule,
+            grid: saved.grid,
+            reference: Reference::default(),
+            phases: RefCell::default(),
+        };
+        Ok((header, client))
+    }
+
+    /// The stored keys, which only a client loaded back from disk lacks
+    fn stored_keys(&self) -> &Keys {
+        match &self.keys {
+            ClientKeys::Stored(keys) => keys,
+            ClientKeys::Saved(_) => panic!("a client loaded back from disk only verifies"),
+        }
+    }
+
+    /// Time spent in every phase so far
+    pub(crate) fn phases(&self) -> Phases {
+        self.phases.borrow().clone()
+    }
+
+    /// Run `f` and add the time it took to `phase`
+    pub(crate) fn timed<T>(&self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.phases
+            .borrow_mut()
+            .add(phase, start.elapsed().as_secs_f64());
+        result
+    }
+
+    /// Keep the time of every generation a server took, and what its grid
+    /// storage allocated and copied in each of them
+    pub(crate) fn record_generations(&self, seconds: Vec<f64>, traffic: Vec<Traffic>) {
+        let mut phases = self.phases.borrow_mut();
+        phases.generations = seconds;
+        phases.traffic = traffic;
+    }
+
+    /// Keep the idle time of a pipelined server
+    pub(crate) fn record_idle(&self, idle: Option<Idle>) {
+        self.phases.borrow_mut().idle = idle;
+    }
+
+    /// Check results against `reference` instead of the bitboard engine
+    pub(crate) fn set_reference(&mut self, reference: Reference) {
+        self.reference = reference;
+    }
+
+    pub(crate) fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    /// The initial grid, in plaintext
+    pub(crate) fn grid(&self) -> &Grid<u8> {
+        &self.grid
+    }
+
+    /// Header describing this client's game
+    pub(crate) fn header(&self, kind: Kind, backend: Backend, steps: u32) -> Header {
+        Header {
+            kind,
+            backend,
+            m: self.grid.dimensions().0 as u32,
+            n: self.grid.dimensions().1 as u32,
+            rule: self.rule,
+            boundary: self.grid.boundary(),
+            steps,
+            compressed: false,
         }
     }
 
     /// Encrypt every instance
     ///
+    /// The cell representation is chosen by `C`, e.g. `FheUint8` for the
+    /// integer server, `FheBool` for the boolean-circuit server or a shortint
+    /// `Ciphertext` for the lookup-table server.
+    ///
     /// # Returns
     /// A tuple containing the server key and the encrypted grid.
-    pub(crate) fn encrypt(&self) -> (ServerKey, EncryptedGrid) {
-        let encrypted_grid = self
-            .grid
-            .iter()
-            .map(|row| {
-                row.iter()
-                    .map(|&cell| FheUint8::encrypt(cell, &self.client_key))
-                    .collect::<Vec<FheUint8>>()
-            })
-            .collect::<Vec<Vec<FheUint8>>>();
-        let server_key = ServerKey::new(&self.client_key);
+    pub(crate) fn encrypt<C: EncryptedCell>(&self) -> (C::ServerKey, EncryptedGrid<C>)
+    where
+        Self: AsRef<C::ClientKey>,
+    {
+        let client_key: &C::ClientKey = self.as_ref();
+        self.timed(Phase::Encrypt, || {
+            let encrypted_grid = self.grid.map(|&cell| C::encrypt_cell(cell, client_key));
+            let server_key = C::server_key(self.stored_keys())
+                .expect(OTHER_BACKEND)
+                .clone();
+
+            (server_key, encrypted_grid)
+        })
+    }
+
+    /// Encrypt every instance into a compressed grid, for a client created
+    /// with `KeyConfig::Compression`
+    ///
+    /// # Returns
+    /// A tuple containing the compressed server key, which was generated
+    /// along with the client's keys, and the compressed grid.
+    pub(crate) fn encrypt_compressed<C: CompressedCell>(
+        &self,
+    ) -> Result<(C::CompressedServerKey, C::CompressedGrid), Box<dyn Error>>
+    where
+        Self: AsRef<C::ClientKey>,
+    {
+        let client_key: &C::ClientKey = self.as_ref();
+        let keys = self.stored_keys();
+        let compressed_server_key = C::compressed_server_key(keys)
+            .ok_or("only a client created with compression keys sends them compressed")?;
+        self.timed(Phase::Encrypt, || {
+            let server_key = C::server_key(keys).expect(OTHER_BACKEND);
+            let grid = C::compress_grid(&self.grid, client_key, server_key)?;
+            Ok((compressed_server_key.clone(), grid))
+        })
+    }
 
-        (server_key, encrypted_grid)
+    /// The compact public key anyone can encrypt cells for this client
+    /// with, and the server key that expands them, for a client created with
+    /// `KeyConfig::PublicKey`
+    ///
+    /// Only the key holder can decrypt what is encrypted with the public key.
+    pub(crate) fn public_keys(&self) -> Result<(ServerKey, CompactPublicKey), Box<dyn Error>> {
+        let public_key = self.timed(Phase::Keygen, || {
+            CompactPublicKey::try_new(self.as_ref())
+                .map_err(|e| format!("the client keys cannot make a compact public key: {e}"))
+        })?;
+        let server_key = self.timed(Phase::Encrypt, || {
+            integer_server_key(self.stored_keys())
+                .ok_or(OTHER_BACKEND)
+                .cloned()
+        })?;
+        Ok((server_key, public_key))
+    }
+
+    /// Take the initial grid from contributions encrypted with the public
+    /// key, for a client that published it without knowing the grid
+    ///
+    /// Expanding the contributions takes the server key the client sent.
+    pub(crate) fn learn_grid<C: PublicCell + EncryptedCell>(
+        &mut self,
+        contributions: &[Contribution],
+        server_key: &ServerKey,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        Self: AsRef<C::ClientKey>,
+    {
+        let grid = public_key::assemble::<C>(
+            self.grid.dimensions(),
+            self.grid.boundary(),
+            contributions,
+            server_key,
+        )?;
+        self.grid = self.decrypt(grid);
+        Ok(())
     }
 
     /// Verify the encrypted grid against the expected state after a number of steps
@@ -54,78 +650,227 @@ impl Client {
     /// * `encrypted_grid` - The encrypted grid to verify.
     /// * `steps` - The number of steps to simulate.
     /// # Returns
-    /// A boolean indicating whether the verification was successful.
-    pub(crate) fn verify(&self, encrypted_grid: EncryptedGrid, steps: u32) -> bool {
+    /// The expected and decrypted grids and the cells where they differ.
+    pub(crate) fn verify<C: EncryptedCell>(
+        &self,
+        encrypted_grid: EncryptedGrid<C>,
+        steps: u32,
+    ) -> Verification
+    where
+        Self: AsRef<C::ClientKey>,
+    {
         let decrypted_grid = self.decrypt(encrypted_grid);
-        let expected_grid = self.grid_after_steps(steps);
+        self.timed(Phase::Verify, || {
+            let expected_grid = self.grid_after_steps(steps);
+            Verification::new(expected_grid, decrypted_grid)
+        })
+    }
 
-        for i in 0..self.grid.len() {
-            for j in 0..self.grid[i].len() {
-                if expected_grid[i][j] != decrypted_grid[i][j] {
-                    return false;
+    /// Verify every generation a server went through
+    /// # Arguments
+    /// * `generations` - The encrypted grids after 0, 1, 2, ... steps.
+    /// # Returns
+    /// The verification of the last generation, along with the first
+    /// generation at which the server diverged from the simulation.
+    pub(crate) fn verify_generations<C: EncryptedCell>(
+        &self,
+        generations: Vec<EncryptedGrid<C>>,
+    ) -> Verification
+    where
+        Self: AsRef<C::ClientKey>,
+    {
+        let mut expected = self.grid.clone();
+        let mut diverged_at = None;
+        let mut last = None;
+        for (generation, encrypted_grid) in generations.into_iter().enumerate() {
+            let decrypted_grid = self.decrypt(encrypted_grid);
+            let verification = self.timed(Phase::Verify, || {
+                if generation > 0 {
+                    expected = self.reference.after(&expected, self.rule, 1);
                 }
+                Verification::new(expected.clone(), decrypted_grid)
+            });
+            if !verification.passed() && diverged_at.is_none() {
+                diverged_at = Some(generation as u32);
             }
+            last = Some(verification);
         }
 
-        true
+        let last = last.expect("generations start with the initial grid");
+        Verification {
+            diverged_at,
+            ..last
+        }
     }
 
-    fn decrypt(&self, encrypted_grid: EncryptedGrid) -> Vec<Vec<u8>> {
-        encrypted_grid
-            .iter()
-            .map(|row| {
-                row.iter()
-                    .map(|cell| FheUint8::decrypt(cell, &self.client_key))
-                    .collect::<Vec<u8>>()
-            })
-            .collect::<Vec<Vec<u8>>>()
-    }
-
-    fn grid_after_steps(&self, steps: u32) -> Vec<Vec<u8>> {
-        let mut current_grid = self.grid.clone();
-        for _ in 0..steps {
-            current_grid = self.next_generation(&current_grid);
-        }
-        current_grid
-    }
-
-    fn next_generation(&self, grid: &Vec<Vec<u8>>) -> Vec<Vec<u8>> {
-        let mut new_grid = grid.clone();
-        let directions = [
-            (-1, -1),
-            (-1, 0),
-            (-1, 1),
-            (0, -1),
-            (0, 1),
-            (1, -1),
-            (1, 0),
-            (1, 1),
-        ];
-
-        for i in 0..grid.len() {
-            for j in 0..grid[i].len() {
-                let mut live_neighbors = 0;
-                for &(dx, dy) in &directions {
-                    let ni = i as isize + dx;
-                    let nj = j as isize + dy;
-                    if ni >= 0 && ni < grid.len() as isize && nj >= 0 && nj < grid[i].len() as isize
-                    {
-                        live_neighbors += grid[ni as usize][nj as usize];
+    fn decrypt<C: EncryptedCell>(&self, encrypted_grid: EncryptedGrid<C>) -> Grid<u8>
+    where
+        Self: AsRef<C::ClientKey>,
+    {
+        let client_key: &C::ClientKey = self.as_ref();
+        self.timed(Phase::Decrypt, || {
+            encrypted_grid.map(|cell| cell.decrypt_cell(client_key))
+        })
+    }
+
+    fn grid_after_steps(&self, steps: u32) -> Grid<u8> {
+        self.reference.after(&self.grid, self.rule, steps)
+    }
+}
+
+/// A plaintext engine every server is checked against, they all compute
+/// the same generations
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Reference {
+    /// `next_generation`, one cell after the other
+    CellByCell,
+    /// `Bitboard`, 64 cells at a time
+    #[default]
+    Bitboard,
+    /// `Hashlife`, jumping straight to the last generation
+    Hashlife,
+}
+
+impl Reference {
+    /// `grid` after `steps` generations
+    pub(crate) fn after(self, grid: &Grid<u8>, rule: Rule, steps: u32) -> Grid<u8> {
+        match self {
+            Reference::CellByCell => {
+                (0..steps).fold(grid.clone(), |grid, _| next_generation(&grid, rule))
+            }
+            Reference::Bitboard => Bitboard::from_grid(grid).run(rule, steps).to_grid(),
+            Reference::Hashlife => Hashlife::new(rule).after(grid, steps),
+        }
+    }
+
+    /// The window of `grid` after `steps` generations of its cells on an
+    /// unbounded plane, whatever the boundary of `grid`
+    ///
+    /// Hashlife evolves the plane itself. The other engines evolve the grid
+    /// padded with `steps` dead cells on every side, the dead border cannot
+    /// reach the window through them in time.
+    pub(crate) fn after_on_plane(self, grid: &Grid<u8>, rule: Rule, steps: u32) -> Grid<u8> {
+        if self == Reference::Hashlife {
+            return Hashlife::new(rule).after_on_plane(grid, steps);
+        }
+
+        let ((m, n), pad) = (grid.dimensions(), steps as usize);
+        let padded = Grid::from_fn((m + 2 * pad, n + 2 * pad), Boundary::Dead, |i, j| {
+            let inside = (pad..pad + m).contains(&i) && (pad..pad + n).contains(&j);
+            if inside { grid[(i - pad, j - pad)] } else { 0 }
+        });
+        let padded = self.after(&padded, rule, steps);
+        Grid::from_fn((m, n), grid.boundary(), |i, j| padded[(i + pad, j + pad)])
+    }
+}
+
+impl FromStr for Reference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cell" => Ok(Reference::CellByCell),
+            "bitboard" => Ok(Reference::Bitboard),
+            "hashlife" => Ok(Reference::Hashlife),
+            _ => Err(format!(
+                "unknown reference `{s}`, expected `cell`, `bitboard` or `hashlife`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reference::CellByCell => "cell",
+            Reference::Bitboard => "bitboard",
+            Reference::Hashlife => "hashlife",
+        })
+    }
+}
+
+/// One generation computed cell by cell, the plainest form of the
+/// reference and what the other engines are checked against
+pub(crate) fn next_generation(grid: &Grid<u8>, rule: Rule) -> Grid<u8> {
+    Grid::from_fn(grid.dimensions(), grid.boundary(), |i, j| {
+        rule.next(grid[(i, j)], grid.neighbors(i, j).sum())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keystore;
+    use crate::server::Evolve;
+    use crate::shortint_server::ShortintServer;
+
+    const ENGINES: [Reference; 2] = [Reference::Bitboard, Reference::Hashlife];
+
+    #[test]
+    fn engines_agree_on_every_boundary() {
+        let rules = ["B3/S23", "B36/S23", "B2/S", "B1357/S1357"];
+        // Rows shorter than, as long as and longer than a bitboard word
+        let dimensions = [(1, 1), (3, 64), (9, 70), (33, 5)];
+
+        for (seed, rule) in rules.iter().enumerate() {
+            let rule: Rule = rule.parse().unwrap();
+            for boundary in [Boundary::Dead, Boundary::Torus, Boundary::Reflect] {
+                for (m, n) in dimensions {
+                    let grid = Grid::from_rows(random_grid(m, n, seed as u64), boundary);
+                    for steps in [0, 1, 7] {
+                        let expected = Reference::CellByCell.after(&grid, rule, steps);
+                        for engine in ENGINES {
+                            assert_eq!(
+                                engine.after(&grid, rule, steps),
+                                expected,
+                                "{engine} after {steps} steps of {rule} on a {m}x{n} {boundary} grid"
+                            );
+                        }
                     }
                 }
+            }
+        }
+    }
+
+    #[test]
+    fn encrypted_server_agrees_on_every_boundary() {
+        let rule = Rule::default();
+        // A corner that dies, wraps around or is mirrored back
+        let rows = vec![vec![1, 1, 0], vec![1, 0, 0], vec![0, 0, 1]];
+        let mut expected = vec![];
+        for boundary in [Boundary::Dead, Boundary::Torus, Boundary::Reflect] {
+            let mut client =
+                Client::with_keys(rows.clone(), rule, boundary, keystore::shortint_test_keys());
+            client.set_reference(Reference::CellByCell);
+            let after = client.grid_after_steps(1);
+            assert!(
+                !expected.contains(&after),
+                "{boundary} looks like another boundary"
+            );
+            expected.push(after);
+
+            let (server_key, grid) = client.encrypt::<Ciphertext>();
+            let server = ShortintServer::new(server_key, grid, rule, 1);
+            let verification = client.verify(server.run(1), 1);
+            assert!(verification.passed(), "{boundary}: {verification}");
+        }
+    }
 
-                if grid[i][j] == 1 {
-                    new_grid[i][j] = if live_neighbors < 2 || live_neighbors > 3 {
-                        0
-                    } else {
-                        1
-                    };
-                } else {
-                    new_grid[i][j] = if live_neighbors == 3 { 1 } else { 0 };
+    #[test]
+    fn engines_agree_on_the_plane() {
+        let rule = Rule::default();
+        for seed in 0..5 {
+            let grid = Grid::from_rows(random_grid(12, 20, seed), Boundary::Torus);
+            for steps in [1, 5, 16] {
+                let expected = Reference::CellByCell.after_on_plane(&grid, rule, steps);
+                for engine in ENGINES {
+                    assert_eq!(
+                        engine.after_on_plane(&grid, rule, steps),
+                        expected,
+                        "{engine} after {steps} steps on the plane, seed {seed}"
+                    );
                 }
             }
         }
-
-        new_grid
     }
 }
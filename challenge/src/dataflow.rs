@@ -0,0 +1,332 @@
+use crate::phases::{Idle, Traffic};
+use rayon::{Scope, ThreadPool};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+/// A DAG of tasks, each computing one value from the values of its inputs
+///
+/// A run starts every task as soon as its last input is ready instead of
+/// stage by stage, so the first tasks of a stage overlap the last ones of
+/// the stage before and no worker waits at a barrier. Stages only matter to
+/// the `Schedule` a run reports.
+///
+/// A value is dropped as soon as the last task reading it is done, unless
+/// it is kept, so only the few stages in flight take up memory.
+pub(crate) struct Dataflow<N> {
+    /// `None` for the sources, whose values are given to the run
+    tasks: Vec<Option<N>>,
+    stages: Vec<usize>,
+    inputs: Vec<Vec<usize>>,
+    /// Tasks reading the value of every task, as many times as they read it
+    consumers: Vec<Vec<usize>>,
+    kept: Vec<bool>,
+}
+
+/// Shared state of one run of a `Dataflow`
+struct Run<T, F> {
+    eval: F,
+    values: Vec<Mutex<Option<Arc<T>>>>,
+    /// Inputs every task still waits for
+    pending: Vec<AtomicUsize>,
+    /// Reads of every value still to come
+    readers: Vec<AtomicUsize>,
+    spans: Vec<OnceLock<Span>>,
+    start: Instant,
+}
+
+impl<N> Default for Dataflow<N> {
+    fn default() -> Self {
+        Dataflow {
+            tasks: vec![],
+            stages: vec![],
+            inputs: vec![],
+            consumers: vec![],
+            kept: vec![],
+        }
+    }
+}
+
+impl<N: Sync> Dataflow<N> {
+    /// Add a value that is given to the run instead of computed
+    pub(crate) fn source(&mut self, stage: usize) -> usize {
+        self.push(None, stage, vec![])
+    }
+
+    /// Add a task reading the values of `inputs`, which have to be added
+    /// already, in this order
+    ///
+    /// # Returns
+    /// The index of the task, and of its value once the graph is run.
+    pub(crate) fn add(&mut self, task: N, stage: usize, inputs: Vec<usize>) -> usize {
+        assert!(!inputs.is_empty(), "tasks without inputs are sources");
+        self.push(Some(task), stage, inputs)
+    }
+
+    /// Have the run return the value of `task`
+    pub(crate) fn keep(&mut self, task: usize) {
+        self.kept[task] = true;
+    }
+
+    fn push(&mut self, task: Option<N>, stage: usize, inputs: Vec<usize>) -> usize {
+        let index = self.tasks.len();
+        for &input in &inputs {
+            assert!(input < index, "inputs have to be added before their tasks");
+            self.consumers[input].push(index);
+        }
+        self.tasks.push(task);
+        self.stages.push(stage);
+        self.inputs.push(inputs);
+        self.consumers.push(vec![]);
+        self.kept.push(false);
+        index
+    }
+
+    /// Evaluate every task on `pool`, `eval` gets a task and the values of
+    /// its inputs
+    ///
+    /// # Arguments
+    /// * `sources` - The values of the sources, in the order they were added.
+    ///
+    /// # Returns
+    /// The value of every kept task, by index, when each task ran and the
+    /// tables of values the run allocated. Values are only ever moved, a
+    /// run cannot copy one.
+    pub(crate) fn run<T, F>(
+        &self,
+        pool: &ThreadPool,
+        sources: Vec<T>,
+        eval: F,
+    ) -> (Vec<Option<T>>, Schedule, Traffic)
+    where
+        T: Send + Sync,
+        F: Fn(&N, &[&T]) -> T + Sync,
+    {
+        let len = self.tasks.len();
+        let run = Run {
+            eval,
+            values: (0..len).map(|_| Mutex::default()).collect(),
+            pending: self
+                .inputs
+                .iter()
+                .map(|inputs| AtomicUsize::new(inputs.len()))
+                .collect(),
+            readers: self
+                .consumers
+                .iter()
+                .map(|consumers| AtomicUsize::new(consumers.len()))
+                .collect(),
+            spans: (0..len).map(|_| OnceLock::new()).collect(),
+            start: Instant::now(),
+        };
+
+        let source_tasks: Vec<usize> = (0..len)
+            .filter(|&task| self.tasks[task].is_none())
+            .collect();
+        assert_eq!(
+            source_tasks.len(),
+            sources.len(),
+            "every source needs a value"
+        );
+        for (&task, value) in source_tasks.iter().zip(sources) {
+            *run.values[task].lock().unwrap() = Some(Arc::new(value));
+            let _ = run.spans[task].set(Span::default());
+        }
+        pool.scope(|scope| {
+            for &task in &source_tasks {
+                self.notify(task, &run, scope);
+            }
+        });
+
+        let schedule = Schedule {
+            threads: pool.current_num_threads(),
+            stages: self.stages.clone(),
+            spans: run
+                .spans
+                .into_iter()
+                .map(|span| span.into_inner().expect("every task ran"))
+                .collect(),
+        };
+        let values = run
+            .values
+            .into_iter()
+            .zip(&self.kept)
+            .map(|(value, &kept)| {
+                let value = value.into_inner().unwrap().filter(|_| kept)?;
+                Some(Arc::into_inner(value).expect("no task holds a value after the run"))
+            })
+            .collect();
+        // The shared table of values and the one the kept values move into
+        let traffic = Traffic {
+            allocations: 2,
+            copied: 0,
+        };
+        (values, schedule, traffic)
+    }
+
+    /// Evaluate `task`, release the inputs it was the last reader of and
+    /// start the consumers it was the last input of
+    fn execute<'s, T, F>(&'s self, task: usize, run: &'s Run<T, F>, scope: &Scope<'s>)
+    where
+        T: Send + Sync,
+        F: Fn(&N, &[&T]) -> T + Sync,
+    {
+        let begin = run.start.elapsed().as_secs_f64();
+        let inputs: Vec<Arc<T>> = self.inputs[task]
+            .iter()
+            .map(|&input| {
+                let value = run.values[input].lock().unwrap();
+                Arc::clone(value.as_ref().expect("inputs are ready"))
+            })
+            .collect();
+        let values: Vec<&T> = inputs.iter().map(|input| &**input).collect();
+        let task_value = self.tasks[task]
+            .as_ref()
+            .expect("sources are not evaluated");
+        let value = (run.eval)(task_value, &values);
+        let end = run.start.elapsed().as_secs_f64();
+        drop(values);
+        drop(inputs);
+
+        for &input in &self.inputs[task] {
+            if run.readers[input].fetch_sub(1, Ordering::AcqRel) == 1 && !self.kept[input] {
+                run.values[input].lock().unwrap().take();
+            }
+        }
+        *run.values[task].lock().unwrap() = Some(Arc::new(value));
+        let span = Span {
+            worker: rayon::current_thread_index(),
+            begin,
+            end,
+        };
+        let _ = run.spans[task].set(span);
+        self.notify(task, run, scope);
+    }
+
+    /// Start every consumer `task` was the last input of
+    fn notify<'s, T, F>(&'s self, task: usize, run: &'s Run<T, F>, scope: &Scope<'s>)
+    where
+        T: Send + Sync,
+        F: Fn(&N, &[&T]) -> T + Sync,
+    {
+        for &consumer in &self.consumers[task] {
+            if run.pending[consumer].fetch_sub(1, Ordering::AcqRel) == 1 {
+                scope.spawn(move |scope| self.execute(consumer, run, scope));
+            }
+        }
+    }
+}
+
+/// When every task of a `Dataflow` run started and finished, in seconds
+/// since the run started
+#[derive(Clone, Debug)]
+pub(crate) struct Schedule {
+    threads: usize,
+    stages: Vec<usize>,
+    spans: Vec<Span>,
+}
+
+/// Where and when one task ran
+#[derive(Clone, Copy, Debug, Default)]
+struct Span {
+    /// Index of the worker in the pool, `None` for the sources
+    worker: Option<usize>,
+    begin: f64,
+    end: f64,
+}
+
+impl Schedule {
+    /// Seconds from the start of the run until its last task finished
+    pub(crate) fn makespan(&self) -> f64 {
+        self.spans.iter().map(|span| span.end).fold(0.0, f64::max)
+    }
+
+    /// When the last task of every stage finished, by stage
+    pub(crate) fn stage_ends(&self) -> Vec<f64> {
+        let mut ends = vec![0.0; self.stages.iter().max().map_or(0, |&stage| stage + 1)];
+        for (&stage, span) in self.stages.iter().zip(&self.spans) {
+            ends[stage] = f64::max(ends[stage], span.end);
+        }
+        ends
+    }
+
+    /// Seconds every worker spent evaluating tasks, by worker
+    fn busy(&self, threads: usize) -> Vec<f64> {
+        let mut busy = vec![0.0; threads];
+        for span in &self.spans {
+            if let Some(worker) = span.worker {
+                busy[worker] += span.end - span.begin;
+            }
+        }
+        busy
+    }
+
+    /// Thread-seconds the workers spent waiting, in this run and at least in
+    /// the same run with a barrier after every stage
+    ///
+    /// The run is measured, every worker waits from the start of the run to
+    /// its last task for as long as it was not evaluating one. The barrier
+    /// run is not run but bounded from below: no schedule finishes a stage
+    /// before its longest task, nor before its tasks spread evenly over the
+    /// workers would, and a real one also leaves gaps between tasks.
+    pub(crate) fn idle(&self) -> Idle {
+        let threads = self.threads.max(1);
+        let stages = self.stage_ends().len();
+        let (mut total, mut longest) = (vec![0.0; stages], vec![0.0f64; stages]);
+        for (&stage, span) in self.stages.iter().zip(&self.spans) {
+            total[stage] += span.end - span.begin;
+            longest[stage] = longest[stage].max(span.end - span.begin);
+        }
+        let barrier_makespan: f64 = total
+            .iter()
+            .zip(longest)
+            .map(|(total, longest)| f64::max(total / threads as f64, longest))
+            .sum();
+
+        let makespan = self.makespan();
+        let busy = self.busy(threads);
+        Idle {
+            pipelined: busy.iter().map(|busy| (makespan - busy).max(0.0)).sum(),
+            // Across the workers rather than per worker, one busier than the
+            // bound would otherwise count as idle in the barrier run
+            barrier: threads as f64 * barrier_makespan - busy.iter().sum::<f64>(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(worker: usize, begin: f64, end: f64) -> Span {
+        Span {
+            worker: Some(worker),
+            begin,
+            end,
+        }
+    }
+
+    #[test]
+    fn idle_is_measured_per_worker_and_never_negative() {
+        // Two workers and three tasks in two stages, with a gap of 1 s the
+        // scheduler left on worker 0 between its two tasks
+        let schedule = Schedule {
+            threads: 2,
+            stages: vec![0, 1, 1, 2],
+            spans: vec![
+                Span::default(),
+                span(0, 0.0, 2.0),
+                span(1, 0.0, 1.0),
+                span(0, 3.0, 4.0),
+            ],
+        };
+        let idle = schedule.idle();
+
+        // Worker 0 waits 1 s, worker 1 the 3 s after its only task
+        assert_eq!(idle.pipelined, 4.0);
+        // A barrier run takes at least 2 s and 1 s, leaving worker 1 idle
+        // for 2 s
+        assert_eq!(idle.barrier, 2.0);
+        assert_eq!(idle.removed(), 0.0);
+    }
+}
@@ -0,0 +1,545 @@
+use crate::backend::Backend;
+use crate::bool_server::BoolServer;
+use crate::client::{Client, CompressedCell, EncryptedGrid, Reference};
+use crate::format::{self, Header, Kind};
+use crate::grid::Grid;
+use crate::phases::Phase;
+use crate::public_key::{self, Contribution};
+use crate::server::{Evolve, Server};
+use crate::shortint_server::ShortintServer;
+use crate::verification::Verification;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use tfhe::shortint::Ciphertext;
+use tfhe::{CompactPublicKey, FheBool, FheUint8, ServerKey};
+
+/// The client's secret keys and plaintext grid, stays with the client
+pub(crate) const CLIENT_FILE: &str = "client.bin";
+pub(crate) const KEY_FILE: &str = "key.bin";
+pub(crate) const GRID_FILE: &str = "grid.bin";
+pub(crate) const RESULT_FILE: &str = "result.bin";
+/// The compact public key contributors encrypt regions of the grid with
+pub(crate) const PUBLIC_KEY_FILE: &str = "public.bin";
+
+/// Size in bytes of every artifact exchanged for one game
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Sizes {
+    pub(crate) key: usize,
+    pub(crate) grid: usize,
+    pub(crate) result: usize,
+}
+
+/// Save the client and write its encrypted inputs to `dir`
+///
+/// This is the first half of the client process, `verify` is the second one.
+pub(crate) fn encrypt(
+    dir: &Path,
+    client: &Client,
+    backend: Backend,
+    compress: bool,
+) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    client.save(&dir.join(CLIENT_FILE), backend)?;
+
+    let key = File::create(dir.join(KEY_FILE))?;
+    let grid = File::create(dir.join(GRID_FILE))?;
+    write_inputs(client, backend, compress, key, grid)
+}
+
+/// Encrypt the client's grid for `backend` and write the server key and the
+/// grid in the file format, compressed if `compress` is set
+pub(crate) fn write_inputs(
+    client: &Client,
+    backend: Backend,
+    compress: bool,
+    key: impl Write,
+    grid: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    match backend {
+        Backend::Integer => write_inputs_as::<FheUint8>(client, backend, compress, key, grid),
+        Backend::Boolean => write_inputs_as::<FheBool>(client, backend, compress, key, grid),
+        Backend::Shortint => write_inputs_as::<Ciphertext>(client, backend, compress, key, grid),
+    }
+}
+
+fn write_inputs_as<C>(
+    client: &Client,
+    backend: Backend,
+    compress: bool,
+    key: impl Write,
+    grid: impl Write,
+) -> Result<(), Box<dyn Error>>
+where
+    C: CompressedCell + Serialize,
+    C::ServerKey: Serialize,
+    C::CompressedServerKey: Serialize,
+    C::CompressedGrid: Serialize,
+    Client: AsRef<C::ClientKey>,
+{
+    let key_header = Header {
+        compressed: compress,
+        ..client.header(Kind::ServerKey, backend, 0)
+    };
+    let grid_header = Header {
+        compressed: compress,
+        ..client.header(Kind::Grid, backend, 0)
+    };
+
+    if compress {
+        let (server_key, compressed_grid) = client.encrypt_compressed::<C>()?;
+        format::write_to(key, &key_header, &server_key)?;
+        format::write_to(grid, &grid_header, &compressed_grid)
+    } else {
+        let (server_key, encrypted_grid) = client.encrypt::<C>();
+        format::write_to(key, &key_header, &server_key)?;
+        format::write_to(grid, &grid_header, &encrypted_grid)
+    }
+}
+
+/// Read the server key and grid from `dir`, run `steps` generations and
+/// write the result next to them
+///
+/// If `dir` holds contributions instead of a grid, the grid is assembled
+/// from them.
+pub(crate) fn serve(dir: &Path, steps: u32, threads: usize) -> Result<(), Box<dyn Error>> {
+    let key = open(&dir.join(KEY_FILE))?;
+    let contributions = contribution_paths(dir)?;
+    if contributions.is_empty() {
+        let grid = open(&dir.join(GRID_FILE))?;
+        let result = File::create(dir.join(RESULT_FILE))?;
+        return evolve(key, grid, steps, threads, result);
+    }
+
+    if dir.join(GRID_FILE).exists() {
+        return Err(format!(
+            "{} holds both {GRID_FILE} and contributions, remove one of them",
+            dir.display()
+        )
+        .into());
+    }
+    let readers = contributions
+        .iter()
+        .map(|path| open(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let result = File::create(dir.join(RESULT_FILE))?;
+    evolve_contributions(key, readers, steps, threads, result)
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("failed to open {}: {e}", path.display()))
+}
+
+/// Most cell updates a server runs for one game
+///
+/// The integer server builds the tasks of every generation before it starts,
+/// a few hundred bytes per cell update, so a client cannot have it run out
+/// of memory with a huge number of steps.
+const MAX_CELL_UPDATES: u64 = 1 << 20;
+
+/// Run `steps` generations on a server key and grid in the file format,
+/// and write the result grid in the file format
+pub(crate) fn evolve(
+    mut key: impl Read,
+    mut grid: impl Read,
+    steps: u32,
+    threads: usize,
+    result: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let key_header = format::read_header(&mut key, Kind::ServerKey)?;
+    let grid_header = format::read_header(&mut grid, Kind::Grid)?;
+    key_header.ensure_matches(&grid_header)?;
+    check_steps(&grid_header, steps)?;
+
+    let header = Header {
+        kind: Kind::Result,
+        steps,
+        compressed: false,
+        ..grid_header
+    };
+    let rule = header.rule;
+    let headers = (&key_header, &grid_header);
+
+    match header.backend {
+        Backend::Integer => {
+            let (server_key, grid) = read_inputs::<FheUint8>(key, grid, headers)?;
+            let server = Server::new(server_key, grid, rule, threads);
+            format::write_to(result, &header, &server.run(steps))
+        }
+        Backend::Boolean => {
+            let (server_key, grid) = read_inputs::<FheBool>(key, grid, headers)?;
+            let server = BoolServer::new(server_key, grid, rule, threads);
+            format::write_to(result, &header, &server.run(steps))
+        }
+        Backend::Shortint => {
+            let (server_key, grid) = read_inputs::<Ciphertext>(key, grid, headers)?;
+            let server = ShortintServer::new(server_key, grid, rule, threads);
+            format::write_to(result, &header, &server.run(steps))
+        }
+    }
+}
+
+fn check_steps(header: &Header, steps: u32) -> Result<(), Box<dyn Error>> {
+    let updates = steps as u64 * header.m as u64 * header.n as u64;
+    if updates > MAX_CELL_UPDATES {
+        return Err(format!(
+            "{steps} steps on a {}x{} grid are {updates} cell updates, \
+             more than the {MAX_CELL_UPDATES} a server runs",
+            header.m, header.n
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Read the server key and grid payloads, decompressing them if their
+/// headers say so
+fn read_inputs<C>(
+    key: impl Read,
+    grid: impl Read,
+    (key_header, grid_header): (&Header, &Header),
+) -> Result<(C::ServerKey, EncryptedGrid<C>), Box<dyn Error>>
+where
+    C: CompressedCell + DeserializeOwned,
+    C::ServerKey: DeserializeOwned,
+    C::CompressedServerKey: DeserializeOwned,
+    C::CompressedGrid: DeserializeOwned,
+{
+    let server_key = if key_header.compressed {
+        C::decompress_server_key(&format::read_payload(key)?)
+    } else {
+        format::read_payload(key)?
+    };
+
+    let grid = if grid_header.compressed {
+        let (m, n) = (grid_header.m as usize, grid_header.n as usize);
+        let cells = C::decompress_grid(&format::read_payload(grid)?, &server_key)?;
+        if cells.len() != m * n {
+            return Err(format!(
+                "compressed grid holds {} cells, expected {m}x{n}",
+                cells.len()
+            )
+            .into());
+        }
+        Grid::from_cells((m, n), grid_header.boundary, cells)
+    } else {
+        read_grid(grid, grid_header)?
+    };
+    Ok((server_key, grid))
+}
+
+/// Write the server key and the compact public key contributors encrypt
+/// the grid with, for a client created with `KeyConfig::PublicKey`
+pub(crate) fn write_public_keys(
+    client: &Client,
+    backend: Backend,
+    key: impl Write,
+    public_key: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    if backend == Backend::Shortint {
+        return Err(PUBLIC_BACKENDS.into());
+    }
+    let (server_key, compact_key) = client.public_keys()?;
+    format::write_to(
+        key,
+        &client.header(Kind::ServerKey, backend, 0),
+        &server_key,
+    )?;
+    format::write_to(
+        public_key,
+        &client.header(Kind::PublicKey, backend, 0),
+        &compact_key,
+    )
+}
+
+const PUBLIC_BACKENDS: &str = "the public key only encrypts integer and boolean cells";
+
+/// Save a client whose grid is encrypted by contributors, and write the
+/// server key and the public key they encrypt with to `dir`
+///
+/// This replaces `encrypt` for a client created with `KeyConfig::PublicKey`,
+/// `verify` then learns the initial grid from the contributions.
+pub(crate) fn publish(dir: &Path, client: &Client, backend: Backend) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    client.save(&dir.join(CLIENT_FILE), backend)?;
+
+    let key = File::create(dir.join(KEY_FILE))?;
+    let public_key = File::create(dir.join(PUBLIC_KEY_FILE))?;
+    write_public_keys(client, backend, key, public_key)
+}
+
+/// Encrypt `region` with the public key in `dir` and write it next to it,
+/// named after `offset` so that contributors never overwrite each other
+///
+/// # Returns
+/// The path of the contribution.
+pub(crate) fn contribute_to(
+    dir: &Path,
+    region: &[Vec<u8>],
+    offset: (usize, usize),
+) -> Result<PathBuf, Box<dyn Error>> {
+    let public_key = open(&dir.join(PUBLIC_KEY_FILE))?;
+    let path = dir.join(format!(
+        "{CONTRIBUTION_PREFIX}{}-{}.bin",
+        offset.0, offset.1
+    ));
+    contribute(public_key, region, offset, File::create(&path)?)?;
+    Ok(path)
+}
+
+const CONTRIBUTION_PREFIX: &str = "contribution-";
+
+/// The contributions in `dir`, in the order of their names
+fn contribution_paths(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        if name.starts_with(CONTRIBUTION_PREFIX) && name.ends_with(".bin") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Encrypt `region` of the grid, with its top left cell at `offset`, from
+/// nothing but a public key in the file format and write it as a
+/// contribution in the file format
+pub(crate) fn contribute(
+    mut public_key: impl Read,
+    region: &[Vec<u8>],
+    offset: (usize, usize),
+    contribution: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let header = format::read_header(&mut public_key, Kind::PublicKey)?;
+    let compact_key: CompactPublicKey = format::read_payload(public_key)?;
+    let encrypted = match header.backend {
+        Backend::Integer => Contribution::encrypt::<FheUint8>(&compact_key, region, offset)?,
+        Backend::Boolean => Contribution::encrypt::<FheBool>(&compact_key, region, offset)?,
+        Backend::Shortint => return Err(PUBLIC_BACKENDS.into()),
+    };
+    let header = Header {
+        kind: Kind::Contribution,
+        ..header
+    };
+    format::write_to(contribution, &header, &encrypted)
+}
+
+/// Like `evolve`, but assemble the grid from contributions in the file
+/// format, which have to cover every cell exactly once
+pub(crate) fn evolve_contributions<R: Read>(
+    mut key: impl Read,
+    contributions: Vec<R>,
+    steps: u32,
+    threads: usize,
+    result: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let key_header = format::read_header(&mut key, Kind::ServerKey)?;
+    check_steps(&key_header, steps)?;
+    let parts = read_contributions(&key_header, contributions)?;
+
+    let header = Header {
+        kind: Kind::Result,
+        steps,
+        compressed: false,
+        ..key_header
+    };
+    let (m, n) = (header.m as usize, header.n as usize);
+    let (rule, boundary) = (header.rule, header.boundary);
+    let server_key: ServerKey = format::read_payload(key)?;
+
+    match header.backend {
+        Backend::Integer => {
+            let grid = public_key::assemble::<FheUint8>((m, n), boundary, &parts, &server_key)?;
+            let server = Server::new(server_key, grid, rule, threads);
+            format::write_to(result, &header, &server.run(steps))
+        }
+        Backend::Boolean => {
+            let grid = public_key::assemble::<FheBool>((m, n), boundary, &parts, &server_key)?;
+            let server = BoolServer::new(server_key, grid, rule, threads);
+            format::write_to(result, &header, &server.run(steps))
+        }
+        Backend::Shortint => Err(PUBLIC_BACKENDS.into()),
+    }
+}
+
+/// Read contributions in the file format, which have to be for the game
+/// `expected` describes
+fn read_contributions<R: Read>(
+    expected: &Header,
+    contributions: Vec<R>,
+) -> Result<Vec<Contribution>, Box<dyn Error>> {
+    let mut parts = vec![];
+    for mut contribution in contributions {
+        let header = format::read_header(&mut contribution, Kind::Contribution)?;
+        expected.ensure_matches(&header)?;
+        parts.push(format::read_payload(contribution)?);
+    }
+    Ok(parts)
+}
+
+/// Play one game in memory, going through the file format exactly like a
+/// client and server in different processes would
+///
+/// # Returns
+/// The verification of the result and the size of every artifact.
+pub(crate) fn run(
+    client: &Client,
+    backend: Backend,
+    compress: bool,
+    steps: u32,
+    threads: usize,
+) -> Result<(Verification, Sizes), Box<dyn Error>> {
+    let (mut key, mut grid, mut result) = (vec![], vec![], vec![]);
+    write_inputs(client, backend, compress, &mut key, &mut grid)?;
+    client.timed(Phase::Evaluate, || {
+        evolve(&key[..], &grid[..], steps, threads, &mut result)
+    })?;
+
+    let expected = client.header(Kind::Client, backend, 0);
+    let (_, verification) = check_result(client, &expected, Some(steps), &result[..])?;
+    let sizes = Sizes {
+        key: key.len(),
+        grid: grid.len(),
+        result: result.len(),
+    };
+    Ok((verification, sizes))
+}
+
+/// Like `run`, but the grid is split into `contributors` bands of rows,
+/// each encrypted from the public key by a contributor of its own
+///
+/// # Returns
+/// The verification of the result and the size of every artifact, where
+/// the key includes the public key and the grid every contribution.
+pub(crate) fn run_public(
+    client: &Client,
+    backend: Backend,
+    contributors: usize,
+    steps: u32,
+    threads: usize,
+) -> Result<(Verification, Sizes), Box<dyn Error>> {
+    let (mut key, mut public_key, mut result) = (vec![], vec![], vec![]);
+    write_public_keys(client, backend, &mut key, &mut public_key)?;
+
+    let rows: Vec<Vec<u8>> = client.grid().rows().map(<[u8]>::to_vec).collect();
+    let bands = contributors.clamp(1, rows.len());
+    let contributions = client.timed(Phase::Encrypt, || {
+        (0..bands)
+            .map(|band| {
+                let (start, end) = (band * rows.len() / bands, (band + 1) * rows.len() / bands);
+                let mut contribution = vec![];
+                contribute(
+                    &public_key[..],
+                    &rows[start..end],
+                    (start, 0),
+                    &mut contribution,
+                )?;
+                Ok(contribution)
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()
+    })?;
+    client.timed(Phase::Evaluate, || {
+        let readers = contributions.iter().map(Vec::as_slice).collect();
+        evolve_contributions(&key[..], readers, steps, threads, &mut result)
+    })?;
+
+    let expected = client.header(Kind::Client, backend, 0);
+    let (_, verification) = check_result(client, &expected, Some(steps), &result[..])?;
+    let sizes = Sizes {
+        key: key.len() + public_key.len(),
+        grid: contributions.iter().map(Vec::len).sum(),
+        result: result.len(),
+    };
+    Ok((verification, sizes))
+}
+
+/// Check the result in `dir` against the client's plaintext simulation on
+/// the `reference` engine, and that the server ran `steps` generations if
+/// they are given
+///
+/// # Returns
+/// The number of steps the server ran and the verification of its result.
+pub(crate) fn verify(
+    dir: &Path,
+    reference: Reference,
+    steps: Option<u32>,
+) -> Result<(u32, Verification), Box<dyn Error>> {
+    let (client_header, mut client) = Client::load(&dir.join(CLIENT_FILE))?;
+    client.set_reference(reference);
+
+    // A published client never saw the grid, it decrypts what was contributed
+    let contributions = contribution_paths(dir)?;
+    if !contributions.is_empty() {
+        let readers = contributions
+            .iter()
+            .map(|path| open(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parts = read_contributions(&client_header, readers)?;
+        let mut key = open(&dir.join(KEY_FILE))?;
+        client_header.ensure_matches(&format::read_header(&mut key, Kind::ServerKey)?)?;
+        let server_key: ServerKey = format::read_payload(key)?;
+        match client_header.backend {
+            Backend::Integer => client.learn_grid::<FheUint8>(&parts, &server_key)?,
+            Backend::Boolean => client.learn_grid::<FheBool>(&parts, &server_key)?,
+            Backend::Shortint => return Err(PUBLIC_BACKENDS.into()),
+        }
+    }
+
+    let result = open(&dir.join(RESULT_FILE))?;
+    check_result(&client, &client_header, steps, result)
+}
+
+/// Check a result grid in the file format against the client's plaintext
+/// simulation, `expected` describes the game the client set up
+///
+/// # Arguments
+/// * `steps` - The generations the client asked for, a result of any other
+///   number is rejected. `None` takes the server's word for it.
+///
+/// # Returns
+/// The number of steps the server ran and the verification of its result.
+pub(crate) fn check_result(
+    client: &Client,
+    expected: &Header,
+    steps: Option<u32>,
+    mut result: impl Read,
+) -> Result<(u32, Verification), Box<dyn Error>> {
+    let header = format::read_header(&mut result, Kind::Result)?;
+    expected.ensure_matches(&header)?;
+    if let Some(steps) = steps.filter(|&steps| steps != header.steps) {
+        return Err(format!(
+            "the server ran {} steps, but the client asked for {steps}",
+            header.steps
+        )
+        .into());
+    }
+
+    let verification = match header.backend {
+        Backend::Integer => client.verify(read_grid::<FheUint8>(result, &header)?, header.steps),
+        Backend::Boolean => client.verify(read_grid::<FheBool>(result, &header)?, header.steps),
+        Backend::Shortint => client.verify(read_grid::<Ciphertext>(result, &header)?, header.steps),
+    };
+    Ok((header.steps, verification))
+}
+
+fn read_grid<C: DeserializeOwned>(
+    reader: impl Read,
+    header: &Header,
+) -> Result<EncryptedGrid<C>, Box<dyn Error>> {
+    let grid: EncryptedGrid<C> = format::read_payload(reader)?;
+    format::check_dimensions(&grid, header)?;
+    Ok(grid)
+}
@@ -0,0 +1,119 @@
+use crate::backend::Backend;
+use crate::client::KeyConfig;
+use crate::keystore::{KeyStore, Keys};
+use crate::ops::OpCounts;
+use crate::params::ParameterSet;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use tfhe::prelude::*;
+use tfhe::{FheBool, FheUint8, set_server_key};
+
+/// Seconds each step of a game takes on this machine, on a single thread
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Costs {
+    /// The parameter set the keys were generated for
+    pub(crate) parameters: String,
+    /// Copying the server key for the server to take
+    pub(crate) server_key: f64,
+    /// Encrypting a single cell
+    pub(crate) encrypt: f64,
+    pub(crate) addition: f64,
+    pub(crate) comparison: f64,
+    pub(crate) select: f64,
+    pub(crate) bootstrap: f64,
+}
+
+/// Predicted seconds of a game
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Prediction {
+    /// Copying the server key and encrypting the grid
+    pub(crate) setup: f64,
+    pub(crate) evaluation: f64,
+}
+
+impl Prediction {
+    pub(crate) fn total(&self) -> f64 {
+        self.setup + self.evaluation
+    }
+}
+
+impl Costs {
+    /// Measure every cost with the keys `store` holds for `parameters`,
+    /// averaging `samples` runs of each operation
+    pub(crate) fn calibrate(
+        samples: u32,
+        parameters: ParameterSet,
+        store: &KeyStore,
+    ) -> Result<Self, Box<dyn Error>> {
+        let samples = samples.max(1);
+        let keys = store.keys(Backend::Integer, KeyConfig::Plain, parameters)?;
+        let Keys::Integer {
+            client_key,
+            server_key,
+            ..
+        } = keys.as_ref()
+        else {
+            unreachable!("the integer backend has integer keys");
+        };
+
+        let mean = |op: &mut dyn FnMut()| {
+            let start = Instant::now();
+            for _ in 0..samples {
+                op();
+            }
+            start.elapsed().as_secs_f64() / samples as f64
+        };
+
+        let server_key_cost = mean(&mut || drop(server_key.clone()));
+        set_server_key(server_key.clone());
+
+        let a = FheUint8::encrypt(1u8, client_key);
+        let b = FheUint8::encrypt(2u8, client_key);
+        let p = FheBool::encrypt(true, client_key);
+        let q = FheBool::encrypt(false, client_key);
+        let costs = Costs {
+            parameters: parameters.to_string(),
+            server_key: server_key_cost,
+            encrypt: mean(&mut || drop(FheUint8::encrypt(1u8, client_key))),
+            addition: mean(&mut || drop(&a + &b)),
+            comparison: mean(&mut || drop(a.eq(3u8))),
+            select: mean(&mut || drop(p.select(&a, &b))),
+            bootstrap: mean(&mut || drop(&p | &q)),
+        };
+        tfhe::unset_server_key();
+        Ok(costs)
+    }
+
+    pub(crate) fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        Ok(serde_json::from_str(&text)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?)
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text).map_err(|e| format!("failed to write {}: {e}", path.display()).into())
+    }
+
+    /// Predict a game on `cells` cells whose evaluation takes `counts`
+    /// operations, spread evenly over `threads` threads
+    ///
+    /// The setup mirrors the harness: the store loads the keys before the
+    /// first case is timed, so only the copy of the server key that
+    /// `Client::encrypt` hands the server along with the grid counts.
+    pub(crate) fn predict(&self, cells: usize, counts: OpCounts, threads: usize) -> Prediction {
+        let setup = self.server_key + cells as f64 * self.encrypt;
+        let operations = counts.additions as f64 * self.addition
+            + counts.comparisons as f64 * self.comparison
+            + counts.selects as f64 * self.select
+            + counts.bootstraps as f64 * self.bootstrap;
+        Prediction {
+            setup,
+            evaluation: operations / threads.max(1) as f64,
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::backend::Backend;
+use crate::boundary::Boundary;
+use crate::grid::Grid;
+use crate::rule::Rule;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Bumped whenever the layout of `Header` or of a payload changes
+pub(crate) const FORMAT_VERSION: u32 = 3;
+
+const MAGIC: [u8; 4] = *b"GOLF";
+
+/// What a file holds after its header
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Kind {
+    /// The client's secret keys and plaintext grid, never leaves the client
+    Client,
+    /// The server key
+    ServerKey,
+    /// The encrypted initial grid
+    Grid,
+    /// The encrypted grid after `Header::steps` generations
+    Result,
+    /// The compact public key contributors encrypt the grid with
+    PublicKey,
+    /// A region of the initial grid encrypted with the public key
+    Contribution,
+}
+
+/// Everything both sides have to agree on, written in front of every file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Header {
+    pub(crate) kind: Kind,
+    pub(crate) backend: Backend,
+    pub(crate) m: u32,
+    pub(crate) n: u32,
+    pub(crate) rule: Rule,
+    pub(crate) boundary: Boundary,
+    pub(crate) steps: u32,
+    /// Whether the payload is a compressed server key or grid
+    pub(crate) compressed: bool,
+}
+
+impl Header {
+    /// Check that `other` describes the same game as `self`
+    pub(crate) fn ensure_matches(&self, other: &Header) -> Result<(), Box<dyn Error>> {
+        let game = |h: &Header| (h.backend, h.m, h.n, h.rule, h.boundary);
+        if game(self) != game(other) {
+            return Err(format!(
+                "{:?} file is for a {}x{} {:?} grid with {} and {:?} boundary, \
+                 but {:?} file is for a {}x{} {:?} grid with {} and {:?} boundary",
+                self.kind,
+                self.m,
+                self.n,
+                self.backend,
+                self.rule,
+                self.boundary,
+                other.kind,
+                other.m,
+                other.n,
+                other.backend,
+                other.rule,
+                other.boundary,
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Write `header` followed by `payload`
+pub(crate) fn write_to<T: Serialize>(
+    writer: impl Write,
+    header: &Header,
+    payload: &T,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(writer);
+    writer.write_all(&MAGIC)?;
+    bincode::serialize_into(&mut writer, &FORMAT_VERSION)?;
+    bincode::serialize_into(&mut writer, header)?;
+    bincode::serialize_into(&mut writer, payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read the header, which has to be of the `expected` kind
+pub(crate) fn read_header(mut reader: impl Read, expected: Kind) -> Result<Header, Box<dyn Error>> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err("not a Game of Life file".into());
+    }
+
+    let version: u32 = bincode::deserialize_from(&mut reader)?;
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "file format version {version} is not supported, expected {FORMAT_VERSION}"
+        )
+        .into());
+    }
+
+    let header: Header = bincode::deserialize_from(&mut reader)?;
+    if header.m == 0 || header.n == 0 {
+        return Err(format!(
+            "{:?} file is for an empty {}x{} grid",
+            header.kind, header.m, header.n
+        )
+        .into());
+    }
+    if header.kind != expected {
+        return Err(format!(
+            "expected a {expected:?} file, found a {:?} file",
+            header.kind
+        )
+        .into());
+    }
+    Ok(header)
+}
+
+/// Read the payload that follows a header
+pub(crate) fn read_payload<T: DeserializeOwned>(reader: impl Read) -> Result<T, Box<dyn Error>> {
+    Ok(bincode::deserialize_from(reader)?)
+}
+
+pub(crate) fn save<T: Serialize>(
+    path: &Path,
+    header: &Header,
+    payload: &T,
+) -> Result<(), Box<dyn Error>> {
+    write_to(File::create(path)?, header, payload)
+        .map_err(|e| format!("failed to write {}: {e}", path.display()).into())
+}
+
+/// Open `path` and read its header, leaving the reader at the payload
+pub(crate) fn open(
+    path: &Path,
+    expected: Kind,
+) -> Result<(Header, BufReader<File>), Box<dyn Error>> {
+    let context = |e: Box<dyn Error>| format!("failed to read {}: {e}", path.display());
+    let mut reader = BufReader::new(File::open(path).map_err(|e| context(e.into()))?);
+    let header = read_header(&mut reader, expected).map_err(context)?;
+    Ok((header, reader))
+}
+
+pub(crate) fn load<T: DeserializeOwned>(
+    path: &Path,
+    expected: Kind,
+) -> Result<(Header, T), Box<dyn Error>> {
+    let (header, reader) = open(path, expected)?;
+    let payload =
+        read_payload(reader).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    Ok((header, payload))
+}
+
+/// Check that a grid has the dimensions and boundary its header claims
+pub(crate) fn check_dimensions<T>(grid: &Grid<T>, header: &Header) -> Result<(), Box<dyn Error>> {
+    let (m, n) = (header.m as usize, header.n as usize);
+    if m == 0 || n == 0 {
+        return Err(format!("{:?} grid is empty", header.kind).into());
+    }
+    if grid.dimensions() != (m, n) || grid.cells().len() != m * n {
+        return Err(format!(
+            "{:?} grid does not have the {m}x{n} cells its header claims",
+            header.kind
+        )
+        .into());
+    }
+    if grid.boundary() != header.boundary {
+        return Err(format!(
+            "{:?} grid has a {} boundary, its header claims {}",
+            header.kind,
+            grid.boundary(),
+            header.boundary
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use crate::boundary::Boundary;
+use serde::{Deserialize, Serialize};
+use std::ops::Index;
+
+/// A rectangle of cells and what lies beyond its edges
+///
+/// Cells are kept flat in row-major order. The plaintext reference and every
+/// server find the neighbors of a cell through its grid, so a change to the
+/// boundary or the neighborhood applies to all of them at once.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Grid<T> {
+    m: usize,
+    n: usize,
+    boundary: Boundary,
+    cells: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    /// An `m * n` grid holding `f(i, j)` at `(i, j)`
+    pub(crate) fn from_fn(
+        (m, n): (usize, usize),
+        boundary: Boundary,
+        mut f: impl FnMut(usize, usize) -> T,
+    ) -> Self {
+        let cells = (0..m * n).map(|k| f(k / n, k % n)).collect();
+        Self::from_cells((m, n), boundary, cells)
+    }
+
+    /// A grid from its rows, which have to be non-empty and of the same
+    /// length
+    pub(crate) fn from_rows(rows: Vec<Vec<T>>, boundary: Boundary) -> Self {
+        assert!(
+            !rows.is_empty() && !rows[0].is_empty(),
+            "the grid needs at least one cell"
+        );
+        let (m, n) = (rows.len(), rows[0].len());
+        assert!(
+            rows.iter().all(|row| row.len() == n),
+            "the grid has to be rectangular"
+        );
+        Self::from_cells((m, n), boundary, rows.into_iter().flatten().collect())
+    }
+
+    /// A grid from its cells in row-major order, there have to be `m * n`
+    pub(crate) fn from_cells((m, n): (usize, usize), boundary: Boundary, cells: Vec<T>) -> Self {
+        assert_eq!(cells.len(), m * n, "an {m}x{n} grid needs {} cells", m * n);
+        Grid {
+            m,
+            n,
+            boundary,
+            cells,
+        }
+    }
+
+    pub(crate) fn dimensions(&self) -> (usize, usize) {
+        (self.m, self.n)
+    }
+
+    pub(crate) fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    /// Every cell in row-major order
+    pub(crate) fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    pub(crate) fn into_cells(self) -> Vec<T> {
+        self.cells
+    }
+
+    pub(crate) fn rows(&self) -> impl Iterator<Item = &[T]> {
+        self.cells.chunks(self.n)
+    }
+
+    /// The same grid with every cell mapped by `f`
+    pub(crate) fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid<U> {
+        Grid {
+            m: self.m,
+            n: self.n,
+            boundary: self.boundary,
+            cells: self.cells.iter().map(f).collect(),
+        }
+    }
+
+    /// A grid of the same shape and boundary holding `cells` instead
+    pub(crate) fn with_cells<U>(&self, cells: Vec<U>) -> Grid<U> {
+        Grid::from_cells((self.m, self.n), self.boundary, cells)
+    }
+
+    /// The neighbors of `(x, y)`, leaving out those on a dead border
+    pub(crate) fn neighbors(&self, x: usize, y: usize) -> impl Iterator<Item = &T> {
+        self.neighborhood(x, y).flatten()
+    }
+
+    /// The eight neighbor slots of `(x, y)`, see `Boundary::neighborhood`
+    pub(crate) fn neighborhood(&self, x: usize, y: usize) -> impl Iterator<Item = Option<&T>> {
+        self.boundary
+            .neighborhood(x, y, self.m, self.n)
+            .map(|neighbor| neighbor.map(|(nx, ny)| &self[(nx, ny)]))
+    }
+
+    /// Row `x` and the rows before and after it, see
+    /// `Boundary::resolve_around`
+    pub(crate) fn rows_around(&self, x: usize) -> impl Iterator<Item = usize> {
+        self.boundary.resolve_around(x, self.m)
+    }
+
+    /// Column `y` and the columns before and after it, see
+    /// `Boundary::resolve_around`
+    pub(crate) fn columns_around(&self, y: usize) -> impl Iterator<Item = usize> {
+        self.boundary.resolve_around(y, self.n)
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (i, j): (usize, usize)) -> &T {
+        assert!(i < self.m && j < self.n, "({i}, {j}) is outside the grid");
+        &self.cells[i * self.n + j]
+    }
+}
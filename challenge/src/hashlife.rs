@@ -0,0 +1,330 @@
+use crate::boundary::Boundary;
+use crate::grid::Grid;
+use crate::rule::Rule;
+use std::collections::HashMap;
+
+/// Level 0 nodes, i.e. single cells
+const DEAD: u32 = 0;
+const ALIVE: u32 = 1;
+/// A cell that never changes and counts as dead, see `Universe::Walled`
+const WALL: u32 = 2;
+
+/// A square of `2^level` cells on a side, made of its four quadrants in the
+/// order north-west, north-east, south-west, south-east
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Node {
+    level: u8,
+    children: [u32; 4],
+}
+
+/// What surrounds the window a run starts from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Universe {
+    /// An unbounded plane of dead cells
+    Plane,
+    /// A ring of walls right outside the window, which is the dead boundary
+    /// of `Client` as nothing beyond the ring reaches into the window
+    Walled,
+    /// The window repeated in every direction, which is the torus
+    Torus,
+    /// The window mirrored at its edges and then repeated, which is the
+    /// reflecting boundary
+    Reflect,
+}
+
+/// A memoized quadtree evolving a whole universe, Gosper's Hashlife
+///
+/// Identical squares are stored once, and so is the center of every square
+/// after every number of steps it was asked for, so a pattern that repeats
+/// in space or in time takes far fewer updates than cells times steps. A run
+/// jumps straight to the generation it is asked for, and bounded grids are
+/// turned into an unbounded universe that evolves exactly like them inside
+/// the window, see `Universe`.
+pub(crate) struct Hashlife {
+    rule: Rule,
+    /// Cells first, at their own index
+    nodes: Vec<Node>,
+    ids: HashMap<Node, u32>,
+    /// The center of a node after a number of steps
+    results: HashMap<(u32, u64), u32>,
+    /// The empty node of every level
+    empty: Vec<u32>,
+}
+
+impl Hashlife {
+    pub(crate) fn new(rule: Rule) -> Self {
+        let cell = |_| Node {
+            level: 0,
+            children: [0; 4],
+        };
+        Hashlife {
+            rule,
+            nodes: (0..3).map(cell).collect(),
+            ids: HashMap::new(),
+            results: HashMap::new(),
+            empty: vec![DEAD],
+        }
+    }
+
+    /// `grid` after `steps` generations with the grid's own boundary
+    pub(crate) fn after(&mut self, grid: &Grid<u8>, steps: u32) -> Grid<u8> {
+        let universe = match grid.boundary() {
+            Boundary::Dead => Universe::Walled,
+            Boundary::Torus => Universe::Torus,
+            Boundary::Reflect => Universe::Reflect,
+        };
+        self.evolve(grid, universe, steps)
+    }
+
+    /// The window of `grid` after `steps` generations of its cells on an
+    /// unbounded plane
+    pub(crate) fn after_on_plane(&mut self, grid: &Grid<u8>, steps: u32) -> Grid<u8> {
+        self.evolve(grid, Universe::Plane, steps)
+    }
+
+    /// Build the universe around `grid`, advance it and crop the window
+    ///
+    /// The root is a square of `size` cells with the window at
+    /// `(size / 4, size / 4)`. Its center after `steps` steps, which covers
+    /// the window, only depends on the cells of the root as long as the
+    /// steps are at most `size / 4`.
+    fn evolve(&mut self, grid: &Grid<u8>, universe: Universe, steps: u32) -> Grid<u8> {
+        let (m, n) = grid.dimensions();
+        let needed = (4 * steps as usize).max(2 * (m.max(n) + 2));
+        let level = needed.next_power_of_two().trailing_zeros() as u8;
+        let size = 1i64 << level;
+        let layout = Layout {
+            grid,
+            universe,
+            origin: size / 4,
+        };
+
+        let mut built = HashMap::new();
+        let root = self.build(level, (0, 0), &layout, &mut built);
+        let center = self.advance(root, steps as u64);
+        Grid::from_fn(grid.dimensions(), grid.boundary(), |i, j| {
+            (self.cell(center, i as i64, j as i64) == ALIVE) as u8
+        })
+    }
+
+    /// The node of `2^level` cells on a side with its top left corner at
+    /// `(x, y)` of the root
+    fn build(
+        &mut self,
+        level: u8,
+        (x, y): (i64, i64),
+        layout: &Layout,
+        built: &mut HashMap<(u8, i64, i64), u32>,
+    ) -> u32 {
+        let side = 1 << level;
+        if !layout.overlaps((x, y), side) {
+            return self.empty(level);
+        }
+        let key = layout.key(level, (x, y));
+        if let Some(&node) = built.get(&key) {
+            return node;
+        }
+
+        let node = if level == 0 {
+            layout.cell(x, y)
+        } else {
+            let half = side / 2;
+            let children = [(x, y), (x, y + half), (x + half, y), (x + half, y + half)]
+                .map(|corner| self.build(level - 1, corner, layout, built));
+            self.join(children)
+        };
+        built.insert(key, node);
+        node
+    }
+
+    /// The center of `node` after `steps` steps, which can be at most a
+    /// quarter of its side
+    fn advance(&mut self, node: u32, steps: u64) -> u32 {
+        let level = self.nodes[node as usize].level;
+        debug_assert!(level >= 2 && steps <= 1 << (level - 2));
+        if steps == 0 {
+            return self.center(node);
+        }
+        if let Some(&result) = self.results.get(&(node, steps)) {
+            return result;
+        }
+
+        let result = if level == 2 {
+            self.step_4x4(node)
+        } else {
+            // Nine overlapping squares of half the side, each advanced on
+            // its own, then the four squares they form advanced by the rest
+            let quarters = self.grandchildren(node);
+            let first = steps.min(1 << (level - 3));
+            let mut partial = [[0; 3]; 3];
+            for (i, row) in partial.iter_mut().enumerate() {
+                for (j, center) in row.iter_mut().enumerate() {
+                    let square = self.join([
+                        quarters[i][j],
+                        quarters[i][j + 1],
+                        quarters[i + 1][j],
+                        quarters[i + 1][j + 1],
+                    ]);
+                    *center = self.advance(square, first);
+                }
+            }
+
+            let mut children = [0; 4];
+            for (k, child) in children.iter_mut().enumerate() {
+                let (i, j) = (k / 2, k % 2);
+                let square = self.join([
+                    partial[i][j],
+                    partial[i][j + 1],
+                    partial[i + 1][j],
+                    partial[i + 1][j + 1],
+                ]);
+                *child = self.advance(square, steps - first);
+            }
+            self.join(children)
+        };
+        self.results.insert((node, steps), result);
+        result
+    }
+
+    /// The center 2x2 cells of a 4x4 node after one step
+    fn step_4x4(&mut self, node: u32) -> u32 {
+        let cells = self.grandchildren(node);
+        let mut next = [DEAD; 4];
+        for (k, next) in next.iter_mut().enumerate() {
+            let (x, y) = (k / 2 + 1, k % 2 + 1);
+            if cells[x][y] == WALL {
+                *next = WALL;
+                continue;
+            }
+            let count = (x - 1..=x + 1)
+                .flat_map(|i| (y - 1..=y + 1).map(move |j| (i, j)))
+                .filter(|&(i, j)| (i, j) != (x, y) && cells[i][j] == ALIVE)
+                .count();
+            *next = self.rule.next((cells[x][y] == ALIVE) as u8, count as u8) as u32;
+        }
+        self.join(next)
+    }
+
+    /// The 4x4 quarters of the quadrants of `node`, by row and column
+    fn grandchildren(&self, node: u32) -> [[u32; 4]; 4] {
+        let children = self.nodes[node as usize].children;
+        std::array::from_fn(|i| {
+            std::array::from_fn(|j| {
+                let child = children[i / 2 * 2 + j / 2];
+                self.nodes[child as usize].children[i % 2 * 2 + j % 2]
+            })
+        })
+    }
+
+    /// The square of half the side in the middle of `node`
+    fn center(&mut self, node: u32) -> u32 {
+        let quarters = self.grandchildren(node);
+        self.join([
+            quarters[1][1],
+            quarters[1][2],
+            quarters[2][1],
+            quarters[2][2],
+        ])
+    }
+
+    /// Cell `(x, y)` of `node`
+    fn cell(&self, mut node: u32, mut x: i64, mut y: i64) -> u32 {
+        let mut level = self.nodes[node as usize].level;
+        while level > 0 {
+            let half = 1 << (level - 1);
+            let quadrant = (x >= half) as usize * 2 + (y >= half) as usize;
+            node = self.nodes[node as usize].children[quadrant];
+            (x, y, level) = (x % half, y % half, level - 1);
+        }
+        node
+    }
+
+    /// The node of the given quadrants, stored once however often it is
+    /// built
+    fn join(&mut self, children: [u32; 4]) -> u32 {
+        let node = Node {
+            level: self.nodes[children[0] as usize].level + 1,
+            children,
+        };
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+        let id = self.nodes.len() as u32;
+        self.nodes.push(node);
+        self.ids.insert(node, id);
+        id
+    }
+
+    fn empty(&mut self, level: u8) -> u32 {
+        while self.empty.len() <= level as usize {
+            let below = *self.empty.last().unwrap();
+            let node = self.join([below; 4]);
+            self.empty.push(node);
+        }
+        self.empty[level as usize]
+    }
+}
+
+/// Where the window lies in the root and what surrounds it
+struct Layout<'a> {
+    grid: &'a Grid<u8>,
+    universe: Universe,
+    /// Row and column of the root holding cell `(0, 0)` of the window
+    origin: i64,
+}
+
+impl Layout<'_> {
+    /// The state of cell `(x, y)` of the root
+    fn cell(&self, x: i64, y: i64) -> u32 {
+        let (m, n) = self.dimensions();
+        let (i, j) = (x - self.origin, y - self.origin);
+        let inside = (0..m).contains(&i) && (0..n).contains(&j);
+        let ring = (-1..=m).contains(&i) && (-1..=n).contains(&j);
+        let window = |i: i64, j: i64| self.grid[(i as usize, j as usize)] as u32;
+        match self.universe {
+            _ if inside => window(i, j),
+            Universe::Plane => DEAD,
+            Universe::Walled if ring => WALL,
+            Universe::Walled => DEAD,
+            Universe::Torus => window(i.rem_euclid(m), j.rem_euclid(n)),
+            Universe::Reflect => window(fold(i, m), fold(j, n)),
+        }
+    }
+
+    /// Whether the square of `side` cells at `(x, y)` holds anything but
+    /// dead cells
+    fn overlaps(&self, (x, y): (i64, i64), side: i64) -> bool {
+        let (m, n) = self.dimensions();
+        // The walls take one more cell on every side
+        let (first, rows, columns) = match self.universe {
+            Universe::Plane => (self.origin, m, n),
+            Universe::Walled => (self.origin - 1, m + 2, n + 2),
+            Universe::Torus | Universe::Reflect => return true,
+        };
+        x < first + rows && first < x + side && y < first + columns && first < y + side
+    }
+
+    /// Squares with the same key hold the same cells, for a periodic
+    /// universe those a whole number of periods apart
+    fn key(&self, level: u8, (x, y): (i64, i64)) -> (u8, i64, i64) {
+        let (m, n) = self.dimensions();
+        let (i, j) = (x - self.origin, y - self.origin);
+        match self.universe {
+            Universe::Plane | Universe::Walled => (level, x, y),
+            Universe::Torus => (level, i.rem_euclid(m), j.rem_euclid(n)),
+            Universe::Reflect => (level, i.rem_euclid(2 * m), j.rem_euclid(2 * n)),
+        }
+    }
+
+    fn dimensions(&self) -> (i64, i64) {
+        let (m, n) = self.grid.dimensions();
+        (m as i64, n as i64)
+    }
+}
+
+/// Map `i` onto `0..len` the way `Boundary::Reflect` does, on every side
+/// of the window and not just the first step beyond it
+fn fold(i: i64, len: i64) -> i64 {
+    let i = i.rem_euclid(2 * len);
+    if i < len { i } else { 2 * len - 1 - i }
+}
@@ -0,0 +1,212 @@
+use crate::backend::Backend;
+use crate::client::KeyConfig;
+use crate::params::ParameterSet;
+use crate::shortint_server::SHORTINT_PARAMETERS;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tfhe::shortint::{
+    ClientKey as ShortintClientKey, CompressedServerKey as CompressedShortintServerKey,
+    ServerKey as ShortintServerKey,
+};
+use tfhe::{ClientKey, CompressedServerKey, Config, ServerKey};
+
+const MAGIC: [u8; 4] = *b"GOLK";
+
+/// The client and server key of one backend, integer and boolean cells
+/// share theirs
+// Always behind an `Rc`, see `KeyStore::keys`
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum Keys {
+    Integer {
+        client_key: ClientKey,
+        server_key: ServerKey,
+        /// The server key as it is sent compressed, only for
+        /// `KeyConfig::Compression`
+        compressed_server_key: Option<CompressedServerKey>,
+    },
+    Shortint {
+        client_key: ShortintClientKey,
+        server_key: ShortintServerKey,
+        /// The server key as it is sent compressed, only for
+        /// `KeyConfig::Compression`
+        compressed_server_key: Option<CompressedShortintServerKey>,
+    },
+}
+
+impl Keys {
+    /// Keys for `backend`, the shortint ones always have
+    /// `SHORTINT_PARAMETERS` and ignore `config`
+    ///
+    /// With `compressed` the server key is generated compressed and
+    /// decompressed once, so that both forms are the same key.
+    pub(crate) fn generate(backend: Backend, config: Config, compressed: bool) -> Self {
+        match backend {
+            Backend::Integer | Backend::Boolean => {
+                let client_key = ClientKey::generate(config);
+                let compressed_server_key =
+                    compressed.then(|| CompressedServerKey::new(&client_key));
+                Keys::Integer {
+                    server_key: compressed_server_key
+                        .as_ref()
+                        .map_or_else(|| ServerKey::new(&client_key), |key| key.decompress()),
+                    client_key,
+                    compressed_server_key,
+                }
+            }
+            Backend::Shortint => {
+                let client_key = ShortintClientKey::new(SHORTINT_PARAMETERS);
+                let compressed_server_key =
+                    compressed.then(|| CompressedShortintServerKey::new(&client_key));
+                Keys::Shortint {
+                    server_key: compressed_server_key.as_ref().map_or_else(
+                        || ShortintServerKey::new(&client_key),
+                        |key| key.decompress(),
+                    ),
+                    client_key,
+                    compressed_server_key,
+                }
+            }
+        }
+    }
+}
+
+/// Shortint keys for the tests that encrypt, generated once for all of
+/// them
+#[cfg(test)]
+pub(crate) fn shortint_test_keys() -> Rc<Keys> {
+    static KEYS: std::sync::OnceLock<Keys> = std::sync::OnceLock::new();
+    let keys = KEYS.get_or_init(|| {
+        let config = KeyConfig::Plain.config(ParameterSet::Default).unwrap();
+        Keys::generate(Backend::Shortint, config, false)
+    });
+    Rc::new(keys.clone())
+}
+
+/// What a key file holds: the parameters the keys were generated for, and
+/// the keys along with a checksum of them
+#[derive(Serialize, Deserialize)]
+struct StoredKeys {
+    magic: [u8; 4],
+    parameters: Vec<u8>,
+    checksum: u64,
+    keys: Vec<u8>,
+}
+
+/// Keys generated once and kept on disk, so that later runs with the same
+/// parameters load them instead of generating them again
+///
+/// A directory holds one file per backend and parameter set, named after a
+/// hash of the parameters. Keys are also kept in memory once loaded, every
+/// client of a run shares them.
+pub(crate) struct KeyStore {
+    dir: PathBuf,
+    /// Generate the keys of every parameter set once more, replacing the
+    /// stored ones
+    fresh: bool,
+    loaded: RefCell<HashMap<Vec<u8>, Rc<Keys>>>,
+}
+
+impl KeyStore {
+    pub(crate) fn new(dir: PathBuf, fresh: bool) -> Self {
+        KeyStore {
+            dir,
+            fresh,
+            loaded: RefCell::default(),
+        }
+    }
+
+    /// The `keys` of `backend` on `parameters`, generated and stored if
+    /// there are none yet
+    ///
+    /// # Returns
+    /// The keys, or an error if the set cannot make them, or if the stored
+    /// ones are damaged or could not be written.
+    pub(crate) fn keys(
+        &self,
+        backend: Backend,
+        keys: KeyConfig,
+        parameters: ParameterSet,
+    ) -> Result<Rc<Keys>, Box<dyn Error>> {
+        let config = keys.config(parameters)?;
+        let compressed = keys == KeyConfig::Compression;
+        // The integer config already tells compression keys apart
+        let parameters = match backend {
+            Backend::Integer | Backend::Boolean => bincode::serialize(&config)?,
+            Backend::Shortint => bincode::serialize(&(SHORTINT_PARAMETERS, compressed))?,
+        };
+        if let Some(keys) = self.loaded.borrow().get(&parameters) {
+            return Ok(Rc::clone(keys));
+        }
+
+        let path = self
+            .dir
+            .join(format!("keys-{:016x}.bin", checksum(&parameters)));
+        let keys = if path.exists() && !self.fresh {
+            read_keys(&path, &parameters)
+                .map_err(|e| format!("{e}, rerun with --fresh-keys to replace them"))?
+        } else {
+            let keys = Keys::generate(backend, config, compressed);
+            write_keys(&path, &parameters, &keys)
+                .map_err(|e| format!("failed to store keys in {}: {e}", path.display()))?;
+            keys
+        };
+        let keys = Rc::new(keys);
+        self.loaded
+            .borrow_mut()
+            .insert(parameters, Rc::clone(&keys));
+        Ok(keys)
+    }
+}
+
+fn read_keys(path: &Path, parameters: &[u8]) -> Result<Keys, Box<dyn Error>> {
+    let context = |e: &dyn std::fmt::Display| format!("stored keys {}: {e}", path.display());
+    let file = File::open(path).map_err(|e| context(&e))?;
+    let stored: StoredKeys =
+        bincode::deserialize_from(BufReader::new(file)).map_err(|e| context(&e))?;
+    if stored.magic != MAGIC {
+        return Err(context(&"not a key file").into());
+    }
+    if stored.parameters != parameters {
+        return Err(context(&"generated for other parameters").into());
+    }
+    if checksum(&stored.keys) != stored.checksum {
+        return Err(context(&"checksum mismatch, the file is damaged").into());
+    }
+    Ok(bincode::deserialize(&stored.keys).map_err(|e| context(&e))?)
+}
+
+/// Write the keys next to `path` first and move them there once complete,
+/// so that an interrupted run never leaves a partial key file behind
+fn write_keys(path: &Path, parameters: &[u8], keys: &Keys) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let keys = bincode::serialize(keys)?;
+    let stored = StoredKeys {
+        magic: MAGIC,
+        parameters: parameters.to_vec(),
+        checksum: checksum(&keys),
+        keys,
+    };
+
+    let partial = path.with_extension("partial");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    bincode::serialize_into(&mut writer, &stored)?;
+    writer.flush()?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// 64-bit FNV-1a, enough to tell a damaged file from an intact one
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -1,6 +1,49 @@
-#[allow(dead_code, unused_variables)]
+mod backend;
+mod bench;
+mod bitboard;
+mod bool_server;
+mod boundary;
+mod buffer;
+mod cell_server;
+mod circuit;
 mod client;
+mod dataflow;
+mod deploy;
+mod estimate;
+mod format;
+mod grid;
+mod hashlife;
+mod keystore;
+mod many_lut_server;
+mod net;
+mod ops;
+mod params;
+mod pattern;
+mod phases;
+mod public_key;
+mod rule;
 mod server;
+mod shortint_server;
+mod verification;
+
+use backend::Backend;
+use bench::{Case, CaseReport, Report, Timings};
+use boundary::Boundary;
+use client::{KeyConfig, Reference};
+use deploy::Sizes;
+use estimate::Costs;
+use grid::Grid;
+use keystore::KeyStore;
+use params::{ParameterReport, ParameterSet};
+use pattern::Pattern;
+use phases::{Phase, Phases};
+use rule::Rule;
+use server::Evolve;
+use std::error::Error;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::time::Instant;
+use verification::Verification;
 
 // m, n, steps, threshold (seconds) and score
 const TEST_CASES: [(u32, u32, u32, f64, u32); 9] = [
@@ -15,39 +58,978 @@ const TEST_CASES: [(u32, u32, u32, f64, u32); 9] = [
     (17, 17, 5, 196.0, 19),
 ];
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut score = 0;
-    println!("    #      m      n   steps   time (s)   res");
-    println!("----- ------ ------ ------- ---------- -----");
+const USAGE: &str = "\
+usage: sustcsc-rs [OPTIONS]                          run the harness on TEST_CASES or --cases
+       sustcsc-rs client encrypt --size MxN [OPTIONS]  write client.bin, key.bin and grid.bin
+       sustcsc-rs server --steps N [OPTIONS]           read key.bin and grid.bin, or the
+                                                       contributions, write result.bin
+       sustcsc-rs client verify [--steps N] [OPTIONS]  check result.bin against client.bin, and
+                                                       that the server ran N steps if given
+       sustcsc-rs client publish --size MxN [OPTIONS]  write client.bin, key.bin and public.bin,
+                                                       which contributors encrypt the grid with
+       sustcsc-rs contribute --size MxN|--pattern FILE --offset R,C [OPTIONS]
+                                                       encrypt a region of the grid with public.bin
+                                                       into contribution-R-C.bin
+       sustcsc-rs server --listen ADDR [OPTIONS]       serve clients over TCP
+       sustcsc-rs client run --connect ADDR --size MxN --steps N [OPTIONS]
+                                                       encrypt, evolve on a TCP server and verify
+       sustcsc-rs reference --size MxN --steps N [OPTIONS]
+                                                       time the --reference engine against the
+                                                       cell-by-cell one
+       sustcsc-rs compare [OPTIONS]                    time the harness cases with the default
+                                                       parameters and with --params (default:
+                                                       multi-bit)
+
+options:
+    --backend integer|boolean|shortint   cell representation (default: integer)
+    --rule RULE                          rule in B/S notation (default: B3/S23)
+    --boundary dead|torus|reflect        what lies beyond the edges (default: dead)
+    --threads N                          worker threads, 0 for all cores (default: 0)
+    --dir DIR                            where the client and server exchange files (default: .)
+    --tcp                                run the harness through a TCP server on localhost
+    --compress                           send a compressed server key and grid, the harness
+                                         then reports the size of every artifact
+    --contributors N                     have N contributors encrypt bands of rows of the grid
+                                         with the client's compact public key in the harness,
+                                         integer and boolean backends only (default: 0, the
+                                         client encrypts the grid itself)
+    --params SET                         TFHE parameters of the integer and boolean keys:
+                                         default, multi-bit (grouping 2 to 4 bits by --threads),
+                                         multi-bit:G or mMcC for M message and C carry bits,
+                                         e.g. m2c3; the harness prints their nominal security,
+                                         noise distribution and sizes
+    --keys DIR                           where generated keys are stored, later runs with the
+                                         same parameters load them from there (default: keys)
+    --fresh-keys                         generate the keys again and replace the stored ones
+    --seed N                             seed of the random grid, the harness draws case i
+                                         from N + i (default: random)
+    --pattern FILE                       start from an RLE, .cells or Life 1.06 pattern
+                                         instead of a random grid
+    --offset R,C                         where the pattern's top left corner goes, or the
+                                         contributed region's (default: 0,0)
+    --trace                              check every generation in the harness, to find the
+                                         first one at which a failing case diverges
+    --cases FILE                         harness cases from a TOML or JSON file, see cases.toml
+    --runs N                             measured runs per harness case, or grids timed by
+                                         reference (default: 1)
+    --reference cell|bitboard|hashlife   plaintext engine results are checked against
+                                         (default: bitboard)
+    --plane                              have reference evolve the grid on an unbounded plane
+                                         instead of inside its --boundary
+    --warmup N                           unmeasured runs before them (default: 0)
+    --report FILE                        also write the harness results as JSON or CSV
+    --phases                             print how long each phase and generation took, what
+                                         every generation allocated and copied, and a lower
+                                         bound on the idle time pipelining the integer server
+                                         saved
+    --circuit                            evaluate the shortint backend gate by gate with the
+                                         compiled boolean circuit instead of one lookup table
+    --many-lut                           evaluate the shortint backend with two tables of the
+                                         count in one bootstrap, then one combining them
+    --estimate                           predict the integer harness from operation counts on
+                                         cleartext instead of running it
+    --costs FILE                         operation costs for --estimate, measured and written
+                                         there if the file does not exist yet";
+
+struct Options {
+    backend: Backend,
+    rule: Rule,
+    boundary: Boundary,
+    threads: usize,
+    size: Option<(u32, u32)>,
+    steps: Option<u32>,
+    dir: PathBuf,
+    listen: Option<String>,
+    connect: Option<String>,
+    tcp: bool,
+    compress: bool,
+    contributors: usize,
+    parameters: ParameterSet,
+    keys: PathBuf,
+    fresh_keys: bool,
+    seed: Option<u64>,
+    pattern: Option<PathBuf>,
+    offset: (usize, usize),
+    trace: bool,
+    cases: Option<PathBuf>,
+    runs: u32,
+    warmup: u32,
+    report: Option<PathBuf>,
+    phases: bool,
+    circuit: bool,
+    many_lut: bool,
+    estimate: bool,
+    costs: Option<PathBuf>,
+    reference: Reference,
+    plane: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, Box<dyn Error>> {
+        let mut options = Options {
+            backend: Backend::Integer,
+            rule: Rule::default(),
+            boundary: Boundary::Dead,
+            threads: 0,
+            size: None,
+            steps: None,
+            dir: PathBuf::from("."),
+            listen: None,
+            connect: None,
+            tcp: false,
+            compress: false,
+            contributors: 0,
+            parameters: ParameterSet::default(),
+            keys: PathBuf::from("keys"),
+            fresh_keys: false,
+            seed: None,
+            pattern: None,
+            offset: (0, 0),
+            trace: false,
+            cases: None,
+            runs: 1,
+            warmup: 0,
+            report: None,
+            phases: false,
+            circuit: false,
+            many_lut: false,
+            estimate: false,
+            costs: None,
+            reference: Reference::default(),
+            plane: false,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--backend" => options.backend = value()?.parse()?,
+                "--rule" => options.rule = value()?.parse()?,
+                "--boundary" => options.boundary = value()?.parse()?,
+                "--threads" => options.threads = value()?.parse()?,
+                "--steps" => options.steps = Some(value()?.parse()?),
+                "--dir" => options.dir = value()?.into(),
+                "--listen" => options.listen = Some(value()?),
+                "--connect" => options.connect = Some(value()?),
+                "--tcp" => options.tcp = true,
+                "--compress" => options.compress = true,
+                "--contributors" => options.contributors = value()?.parse()?,
+                "--params" => options.parameters = value()?.parse()?,
+                "--keys" => options.keys = value()?.into(),
+                "--fresh-keys" => options.fresh_keys = true,
+                "--trace" => options.trace = true,
+                "--cases" => options.cases = Some(value()?.into()),
+                "--runs" => options.runs = value()?.parse()?,
+                "--warmup" => options.warmup = value()?.parse()?,
+                "--report" => options.report = Some(value()?.into()),
+                "--phases" => options.phases = true,
+                "--circuit" => options.circuit = true,
+                "--many-lut" => options.many_lut = true,
+                "--estimate" => options.estimate = true,
+                "--costs" => options.costs = Some(value()?.into()),
+                "--reference" => options.reference = value()?.parse()?,
+                "--plane" => options.plane = true,
+                "--seed" => options.seed = Some(value()?.parse()?),
+                "--pattern" => options.pattern = Some(value()?.into()),
+                "--offset" => {
+                    let offset = value()?;
+                    let (row, col) = offset
+                        .split_once(',')
+                        .ok_or(format!("offset `{offset}` is not of the form R,C"))?;
+                    options.offset = (row.parse()?, col.parse()?);
+                }
+                "--size" => {
+                    let size = value()?;
+                    let (m, n) = size
+                        .split_once('x')
+                        .ok_or(format!("size `{size}` is not of the form MxN"))?;
+                    let (m, n) = (m.parse()?, n.parse()?);
+                    if m == 0 || n == 0 {
+                        return Err(format!("size `{size}` has no cells").into());
+                    }
+                    options.size = Some((m, n));
+                }
+                _ => return Err(format!("unknown argument `{arg}`\n\n{USAGE}").into()),
+            }
+        }
+
+        if options.threads == 0 {
+            options.threads = rayon::current_num_threads();
+        }
+        options.parameters = options.parameters.resolve(options.threads);
+        Ok(options)
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {e}");
+        std::process::exit(2);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1).peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("client" | "server" | "reference" | "compare" | "contribute") => args.next(),
+        _ => None,
+    };
+    let subcommand = match command.as_deref() {
+        Some("client") => args.next(),
+        _ => None,
+    };
+    let options = Options::parse(args)?;
+    let pattern = options.pattern.as_deref().map(Pattern::load).transpose()?;
+    let seed = options.seed.unwrap_or_else(rand::random);
+
+    match (command.as_deref(), subcommand.as_deref()) {
+        (None, _) if options.estimate => estimate(&options, pattern.as_ref(), seed),
+        (None, _) => harness(&options, pattern.as_ref(), seed),
+        (Some("reference"), _) => reference(&options, pattern.as_ref(), seed),
+        (Some("compare"), _) => compare(&options, pattern.as_ref(), seed),
+        (Some("client"), Some("encrypt")) => {
+            let size = options.size.ok_or("client encrypt needs --size")?;
+            let grid = initial_grid(size, pattern.as_ref(), seed, &options)?;
+            let store = KeyStore::new(options.keys.clone(), options.fresh_keys);
+            let client = new_client(grid, options.rule, options.boundary, &options, &store)?;
+            deploy::encrypt(&options.dir, &client, options.backend, options.compress)?;
+            println!(
+                "wrote the encrypted {}x{} grid{} to {}",
+                size.0,
+                size.1,
+                describe_grid(pattern.as_ref(), seed),
+                options.dir.display()
+            );
+            Ok(())
+        }
+        (Some("client"), Some("publish")) => {
+            let (m, n) = options.size.ok_or("client publish needs --size")?;
+            if options.compress {
+                return Err("the public key is sent as it is, without --compress".into());
+            }
+            // Contributors hold the grid, the client learns it when verifying
+            let grid = vec![vec![0; n as usize]; m as usize];
+            let store = KeyStore::new(options.keys.clone(), options.fresh_keys);
+            let client = client::Client::from_store(
+                grid,
+                options.rule,
+                options.boundary,
+                options.backend,
+                KeyConfig::PublicKey,
+                options.parameters,
+                &store,
+            )?;
+            deploy::publish(&options.dir, &client, options.backend)?;
+            println!(
+                "wrote the keys for a {m}x{n} grid to {}",
+                options.dir.display()
+            );
+            Ok(())
+        }
+        (Some("contribute"), _) => {
+            let region = match pattern.as_ref() {
+                Some(pattern) => pattern.place((pattern.height(), pattern.width()), (0, 0))?,
+                None => {
+                    let (m, n) = options.size.ok_or("contribute needs --size or --pattern")?;
+                    client::random_grid(m, n, seed)
+                }
+            };
+            let path = deploy::contribute_to(&options.dir, &region, options.offset)?;
+            println!(
+                "wrote the encrypted {}x{} region{} at {:?} to {}",
+                region.len(),
+                region.first().map_or(0, Vec::len),
+                describe_grid(pattern.as_ref(), seed),
+                options.offset,
+                path.display()
+            );
+            Ok(())
+        }
+        (Some("server"), _) if options.listen.is_some() => {
+            let listener = TcpListener::bind(options.listen.as_deref().unwrap())?;
+            println!("listening on {}", listener.local_addr()?);
+            Ok(net::listen(listener, options.threads)?)
+        }
+        (Some("server"), _) => {
+            let steps = options.steps.ok_or("server needs --steps")?;
+            deploy::serve(&options.dir, steps, options.threads)?;
+            println!(
+                "wrote the grid after {steps} steps to {}",
+                options.dir.display()
+            );
+            Ok(())
+        }
+        (Some("client"), Some("verify")) => {
+            let (steps, verification) =
+                deploy::verify(&options.dir, options.reference, options.steps)?;
+            if !verification.passed() {
+                println!("FAIL after {steps} steps\n{verification}");
+                std::process::exit(1);
+            }
+            println!("PASS after {steps} steps");
+            Ok(())
+        }
+        (Some("client"), Some("run")) => {
+            let addr = options
+                .connect
+                .as_deref()
+                .ok_or("client run needs --connect")?;
+            let size = options.size.ok_or("client run needs --size")?;
+            let steps = options.steps.ok_or("client run needs --steps")?;
+            let grid = initial_grid(size, pattern.as_ref(), seed, &options)?;
+            let store = KeyStore::new(options.keys.clone(), options.fresh_keys);
+            let client = new_client(grid, options.rule, options.boundary, &options, &store)?;
+            let (verification, _) =
+                net::run(&client, options.backend, options.compress, addr, steps)?;
+            let grid = describe_grid(pattern.as_ref(), seed);
+            if !verification.passed() {
+                println!("FAIL after {steps} steps{grid}\n{verification}");
+                std::process::exit(1);
+            }
+            println!("PASS after {steps} steps{grid}");
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// The pattern placed at `--offset` if there is one, a grid drawn from
+/// `seed` otherwise
+fn initial_grid(
+    (m, n): (u32, u32),
+    pattern: Option<&Pattern>,
+    seed: u64,
+    options: &Options,
+) -> Result<Vec<Vec<u8>>, String> {
+    match pattern {
+        Some(pattern) => pattern.place((m as usize, n as usize), options.offset),
+        None => Ok(client::random_grid(m, n, seed)),
+    }
+}
+
+/// How to get the same initial grid again
+fn describe_grid(pattern: Option<&Pattern>, seed: u64) -> String {
+    match pattern {
+        Some(_) => String::new(),
+        None => format!(" (seed {seed})"),
+    }
+}
+
+/// A client for `grid` with keys from `store`, set up as the options say
+fn new_client(
+    grid: Vec<Vec<u8>>,
+    rule: Rule,
+    boundary: Boundary,
+    options: &Options,
+    store: &KeyStore,
+) -> Result<client::Client, Box<dyn Error>> {
+    let mut client = client::Client::from_store(
+        grid,
+        rule,
+        boundary,
+        options.backend,
+        key_config(options),
+        options.parameters,
+        store,
+    )?;
+    client.set_reference(options.reference);
+    Ok(client)
+}
+
+/// What the client's keys have to do besides encrypting and evaluating
+fn key_config(options: &Options) -> KeyConfig {
+    if options.compress {
+        KeyConfig::Compression
+    } else if options.contributors > 0 {
+        KeyConfig::PublicKey
+    } else {
+        KeyConfig::Plain
+    }
+}
+
+fn harness_cases(options: &Options) -> Result<Vec<Case>, Box<dyn Error>> {
+    match &options.cases {
+        Some(path) => bench::load_cases(path),
+        None => Ok(TEST_CASES.into_iter().map(Case::from).collect()),
+    }
+}
+
+/// Time the `--reference` engine against the cell-by-cell one on `--runs`
+/// grids, drawn from `seed`, `seed + 1`, ... unless a pattern is given
+///
+/// That the engines agree is up to the tests.
+fn reference(
+    options: &Options,
+    pattern: Option<&Pattern>,
+    seed: u64,
+) -> Result<(), Box<dyn Error>> {
+    let (m, n) = options.size.ok_or("reference needs --size")?;
+    let steps = options.steps.ok_or("reference needs --steps")?;
+    let Options {
+        rule,
+        boundary,
+        runs,
+        reference,
+        plane,
+        ..
+    } = *options;
+    let universe = if plane {
+        "an unbounded plane".to_string()
+    } else {
+        format!("boundary: {boundary:?}")
+    };
+    println!("rule: {rule}, {universe}, {m}x{n} grid, {steps} steps");
+
+    let time = |engine: Reference, grid: &Grid<u8>| {
+        let start = Instant::now();
+        if plane {
+            engine.after_on_plane(grid, rule, steps);
+        } else {
+            engine.after(grid, rule, steps);
+        }
+        start.elapsed().as_secs_f64()
+    };
+
+    for run in 0..runs {
+        let run_seed = seed.wrapping_add(run as u64);
+        let grid = initial_grid((m, n), pattern, run_seed, options)?;
+        let grid = Grid::from_rows(grid, boundary);
+
+        let cell_by_cell = time(Reference::CellByCell, &grid);
+        let seconds = time(reference, &grid);
+        println!(
+            "run {run}{}: cell by cell {cell_by_cell:.4} s, {reference} {seconds:.4} s ({:.1}x)",
+            describe_grid(pattern, run_seed),
+            cell_by_cell / seconds,
+        );
+    }
+    Ok(())
+}
+
+/// Time every harness case with the default parameters and with the
+/// `--params` ones, multi-bit with the group chosen for `--threads` if they
+/// are the default too
+///
+/// Both run the servers in-process from stored keys, and the time of a
+/// case is the median over `--runs` after `--warmup`, like in the harness.
+fn compare(options: &Options, pattern: Option<&Pattern>, seed: u64) -> Result<(), Box<dyn Error>> {
+    let Options {
+        backend,
+        rule,
+        boundary,
+        threads,
+        runs,
+        warmup,
+        ..
+    } = *options;
+    if backend == Backend::Shortint {
+        return Err("compare needs the integer or boolean backend".into());
+    }
+    if options.tcp || options.compress || options.contributors > 0 {
+        return Err(
+            "compare runs the server in-process, without --tcp, --compress or --contributors"
+                .into(),
+        );
+    }
+    if runs == 0 {
+        return Err("--runs needs at least one run".into());
+    }
+    let other = match options.parameters {
+        ParameterSet::Default => ParameterSet::multi_bit(threads),
+        parameters => parameters,
+    };
+    let sets = [ParameterSet::Default, other];
+    let cases = harness_cases(options)?;
+    let store = KeyStore::new(options.keys.clone(), options.fresh_keys);
+
+    println!("backend: {backend:?}, rule: {rule}, boundary: {boundary:?}, threads: {threads}");
+    for set in sets {
+        let keys = store.keys(backend, KeyConfig::Plain, set)?;
+        println!("{}", ParameterReport::new(set, &keys)?);
+    }
+    let [first, second] = sets.map(|set| format!("{set} (s)"));
+    println!("    #      m      n   steps {first:>14} {second:>14}  speedup   res");
+    println!("----- ------ ------ ------- -------------- -------------- -------- -----");
+
+    for (i, case) in cases.iter().enumerate() {
+        let Case { m, n, steps, .. } = *case;
+        let rule = case.rule.unwrap_or(rule);
+        let boundary = case.boundary.unwrap_or(boundary);
+        let case_seed = case.seed.unwrap_or(seed.wrapping_add(i as u64));
+        let grid = match initial_grid((m, n), pattern, case_seed, options) {
+            Ok(grid) => grid,
+            Err(e) => {
+                println!("{i:5} {m:6} {n:6} {steps:7} {:>14}  SKIP ({e})", "-");
+                continue;
+            }
+        };
+
+        let mut verified = true;
+        let mut medians = [0.0; 2];
+        for (median, set) in medians.iter_mut().zip(sets) {
+            let mut durations = vec![];
+            for run in 0..warmup + runs {
+                let start = Instant::now();
+                let mut client = client::Client::from_store(
+                    grid.clone(),
+                    rule,
+                    boundary,
+                    backend,
+                    KeyConfig::Plain,
+                    set,
+                    &store,
+                )?;
+                client.set_reference(options.reference);
+                let (verification, _) = play(&client, steps, None, options)?;
+                let duration = start.elapsed().as_secs_f64();
+
+                verified &= verification.passed();
+                if run >= warmup {
+                    durations.push(duration);
+                }
+            }
+            *median = Timings::new(durations)
+                .expect("at least one measured run")
+                .median;
+        }
+
+        println!(
+            "{i:5} {m:6} {n:6} {steps:7} {:14.4} {:14.4} {:7.2}x  {}",
+            medians[0],
+            medians[1],
+            medians[0] / medians[1],
+            if verified { "PASS" } else { "FAIL" }
+        );
+    }
+    Ok(())
+}
+
+/// Predict the harness instead of running it
+///
+/// Every case runs on the cleartext mock server in a few milliseconds, its
+/// result is checked against the plaintext simulation and its operation
+/// counts are turned into a predicted time with costs measured on real keys.
+fn estimate(options: &Options, pattern: Option<&Pattern>, seed: u64) -> Result<(), Box<dyn Error>> {
+    let Options {
+        backend,
+        rule,
+        boundary,
+        threads,
+        ..
+    } = *options;
+    if backend != Backend::Integer {
+        return Err("--estimate models the integer backend only".into());
+    }
+    let cases = harness_cases(options)?;
+
+    let costs = match &options.costs {
+        Some(path) if path.exists() => {
+            let costs = Costs::load(path)?;
+            if costs.parameters != options.parameters.to_string() {
+                return Err(format!(
+                    "the costs in {} were measured on {} parameters, not {}, \
+                     pass another --costs file",
+                    path.display(),
+                    costs.parameters,
+                    options.parameters
+                )
+                .into());
+            }
+            costs
+        }
+        path => {
+            println!("calibrating operation costs on real keys...");
+            let store = KeyStore::new(options.keys.clone(), options.fresh_keys);
+            let costs = Costs::calibrate(5, options.parameters, &store)?;
+            if let Some(path) = path {
+                costs.save(path)?;
+            }
+            costs
+        }
+    };
+    println!(
+        "costs (s) on {} parameters: server key copy {:.3}, \
+         encrypt {:.4}, addition {:.4}, comparison {:.4}, select {:.4}, bootstrap {:.4}",
+        costs.parameters,
+        costs.server_key,
+        costs.encrypt,
+        costs.addition,
+        costs.comparison,
+        costs.select,
+        costs.bootstrap
+    );
+    println!("backend: {backend:?}, rule: {rule}, boundary: {boundary:?}, threads: {threads}");
+    println!(
+        "    #      m      n   steps  additions count adds comparisons    selects bootstraps  \
+         predicted (s)   res"
+    );
+    println!(
+        "----- ------ ------ ------- ---------- ---------- ----------- ---------- ---------- \
+         -------------- -----"
+    );
 
-    for (i, (m, n, steps, threshold, sco)) in TEST_CASES.iter().enumerate() {
-        let start = std::time::Instant::now();
-        let client = client::Client::new(*m, *n);
-        let (server_key, encrypted_grid) = client.encrypt();
-        let server = server::Server::new(server_key, encrypted_grid);
+    let (mut score, mut max_score) = (0, 0);
+    for (i, case) in cases.iter().enumerate() {
+        let Case {
+            m,
+            n,
+            steps,
+            threshold,
+            ..
+        } = *case;
+        let rule = case.rule.unwrap_or(rule);
+        let boundary = case.boundary.unwrap_or(boundary);
+        let case_seed = case.seed.unwrap_or(seed.wrapping_add(i as u64));
+        max_score += case.score;
+        let grid = match initial_grid((m, n), pattern, case_seed, options) {
+            Ok(grid) => grid,
+            Err(e) => {
+                println!("{i:5} {m:6} {n:6} {steps:7} {:>10}  SKIP ({e})", "-");
+                continue;
+            }
+        };
 
-        // Run the server simulation
-        let result_grid = server.run(*steps);
+        let grid = Grid::from_rows(grid, boundary);
+        let server = server::Server::mock(grid.clone(), rule, threads);
+        let expected = options.reference.after(&grid, rule, steps);
+        let verification = Verification::new(expected, server.run(steps));
+        let counts = server.counts();
+        let prediction = costs.predict((m * n) as usize, counts, threads);
 
-        // Verify the result
-        let mut pass = client.verify(result_grid, *steps);
-        let duration = start.elapsed().as_secs_f64();
+        let pass = verification.passed() && prediction.total() <= threshold;
+        score += if pass { case.score } else { 0 };
+        println!(
+            "{:5} {:6} {:6} {:7} {:10} {:10} {:11} {:10} {:10} {:14.4}  {}",
+            i,
+            m,
+            n,
+            steps,
+            counts.additions,
+            // Of which the neighbor count stage
+            server.neighbor_counts().additions,
+            counts.comparisons,
+            counts.selects,
+            counts.bootstraps,
+            prediction.total(),
+            if pass { "PASS" } else { "FAIL" }
+        );
+        if !verification.passed() {
+            println!("{verification}");
+        }
+    }
+
+    println!("Predicted score: {score}/{max_score}");
+    Ok(())
+}
+
+fn harness(options: &Options, pattern: Option<&Pattern>, seed: u64) -> Result<(), Box<dyn Error>> {
+    let Options {
+        backend,
+        rule,
+        boundary,
+        threads,
+        compress,
+        runs,
+        warmup,
+        ..
+    } = *options;
+    let public = options.contributors > 0;
+    if public && (options.tcp || compress) {
+        return Err(
+            "--contributors encrypts with the public key, without --tcp or --compress".into(),
+        );
+    }
+    if public && backend == Backend::Shortint {
+        return Err("--contributors needs the integer or boolean backend".into());
+    }
+    if options.parameters != ParameterSet::Default && backend == Backend::Shortint {
+        return Err("--params sets the integer and boolean keys, not the shortint ones".into());
+    }
+    // Sizes are only known when the artifacts are serialized
+    let serialized = options.tcp || compress || public;
+    let in_process = [
+        ("--trace", options.trace),
+        ("--circuit", options.circuit),
+        ("--many-lut", options.many_lut),
+    ];
+    if let Some((flag, _)) = in_process.iter().find(|&&(_, set)| serialized && set) {
+        return Err(format!(
+            "{flag} needs the server in-process, without --tcp, --compress or --contributors"
+        )
+        .into());
+    }
+    if options.circuit && options.many_lut {
+        return Err(
+            "--circuit and --many-lut are two ways to evaluate the shortint backend".into(),
+        );
+    }
+    if runs == 0 {
+        return Err("--runs needs at least one run".into());
+    }
+    let cases = harness_cases(options)?;
+    let store = KeyStore::new(options.keys.clone(), options.fresh_keys);
 
-        pass &= duration <= *threshold;
-        score += if pass { *sco } else { 0u32 };
+    // Every case goes through the real protocol, against a server on a
+    // background thread
+    let server_addr = if options.tcp {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        std::thread::spawn(move || net::listen(listener, threads));
+        Some(addr)
+    } else {
+        None
+    };
 
+    let mut report = Report {
+        backend: format!("{backend:?}"),
+        parameters: options.parameters.to_string(),
+        threads,
+        warmup,
+        cases: vec![],
+        score: 0,
+        max_score: cases.iter().map(|case| case.score).sum(),
+    };
+    println!(
+        "backend: {backend:?}, rule: {rule}, boundary: {boundary:?}, threads: {threads}{}{}{}",
+        server_addr.map_or(String::new(), |addr| format!(", server: {addr}")),
+        if compress { ", compressed" } else { "" },
+        if public {
+            format!(", {} contributors", options.contributors)
+        } else {
+            String::new()
+        }
+    );
+    if let Some(path) = &options.cases {
+        println!("cases: {}", path.display());
+    }
+    if runs > 1 || warmup > 0 {
+        println!("runs: {runs} after {warmup} warmup runs, time is the median");
+    }
+    match (pattern, &options.pattern) {
+        (Some(pattern), Some(path)) => println!(
+            "pattern: {} ({}x{}) at {:?}",
+            path.display(),
+            pattern.height(),
+            pattern.width(),
+            options.offset
+        ),
+        _ => println!("seed: {seed} (case i starts from seed + i)"),
+    }
+    if backend != Backend::Shortint {
+        // Stored keys, which every case then reuses
+        let keys = store.keys(backend, key_config(options), options.parameters)?;
+        println!("{}", ParameterReport::new(options.parameters, &keys)?);
+    }
+    if backend == Backend::Boolean || options.circuit {
+        // Every circuit the cases compile is checked in the clear first, so a
+        // wrong circuit does not pass for a noise failure
+        for case_rule in cases.iter().filter_map(|case| case.rule) {
+            circuit::check_life(&circuit::life(case_rule), case_rule)?;
+        }
+        let circuit = circuit::life(rule);
+        circuit::check_life(&circuit, rule)?;
         println!(
+            "circuit: {} gates, depth {}, checked on all 512 neighborhoods",
+            circuit.gate_count(),
+            circuit.depth()
+        );
+    }
+    if serialized {
+        println!("    #      m      n   steps   time (s)   res   key (MB)  grid (kB) result (kB)");
+        println!("----- ------ ------ ------- ---------- ----- ---------- ---------- -----------");
+    } else {
+        println!("    #      m      n   steps   time (s)   res");
+        println!("----- ------ ------ ------- ---------- -----");
+    }
+
+    for (i, case) in cases.iter().enumerate() {
+        let Case {
+            m,
+            n,
+            steps,
+            threshold,
+            ..
+        } = *case;
+        let rule = case.rule.unwrap_or(rule);
+        let boundary = case.boundary.unwrap_or(boundary);
+        let case_seed = case.seed.unwrap_or(seed.wrapping_add(i as u64));
+        let grid = match initial_grid((m, n), pattern, case_seed, options) {
+            Ok(grid) => grid,
+            Err(e) => {
+                println!("{i:5} {m:6} {n:6} {steps:7} {:>10}  SKIP ({e})", "-");
+                continue;
+            }
+        };
+
+        let mut durations = vec![];
+        let mut phases = vec![];
+        let mut verified = true;
+        let mut failure = None;
+        let mut sizes = None;
+        for run in 0..warmup + runs {
+            let start = Instant::now();
+            let client = new_client(grid.clone(), rule, boundary, options, &store)?;
+
+            // Run the server simulation and verify the result
+            let (verification, transferred) = play(&client, steps, server_addr, options)?;
+            let duration = start.elapsed().as_secs_f64();
+
+            if run < warmup {
+                continue;
+            }
+            durations.push(duration);
+            phases.push(client.phases());
+            sizes = transferred;
+            if !verification.passed() {
+                verified = false;
+                failure.get_or_insert(verification);
+            }
+        }
+        let timings = Timings::new(durations).expect("at least one measured run");
+
+        let mut pass = verified;
+        pass &= timings.median <= threshold;
+        let score = if pass { case.score } else { 0u32 };
+        report.score += score;
+
+        print!(
             "{:5} {:6} {:6} {:7} {:10.4}  {}",
             i,
             m,
             n,
             steps,
-            duration,
+            timings.median,
             if pass { "PASS" } else { "FAIL" }
         );
+        match sizes {
+            Some(sizes) => println!(
+                " {:10.2} {:10.2} {:11.2}",
+                sizes.key as f64 / 1e6,
+                sizes.grid as f64 / 1e3,
+                sizes.result as f64 / 1e3
+            ),
+            None => println!(),
+        }
+        let phases = Phases::mean(&phases);
+        if options.phases {
+            println!("      {phases}");
+        }
+        if let Some(verification) = failure {
+            println!("{verification}");
+        }
+
+        let mut case_report = CaseReport {
+            case: i,
+            m,
+            n,
+            steps,
+            rule: rule.to_string(),
+            boundary: boundary.to_string(),
+            seed: pattern.is_none().then_some(case_seed),
+            runs,
+            timings,
+            phases,
+            threshold,
+            verified,
+            pass,
+            score,
+            key_bytes: None,
+            grid_bytes: None,
+            result_bytes: None,
+        };
+        case_report.set_sizes(sizes);
+        report.cases.push(case_report);
     }
 
-    println!("Score: {}/86", score);
+    println!("Score: {}/{}", report.score, report.max_score);
+    if let Some(path) = &options.report {
+        report.save(path)?;
+        println!("wrote the report to {}", path.display());
+    }
 
     Ok(())
 }
+
+/// Have a server run `steps` generations on the client's grid and verify
+/// the result, through TCP or the file format if the options ask for it
+///
+/// # Returns
+/// The verification and, if the artifacts were serialized, their sizes.
+fn play(
+    client: &client::Client,
+    steps: u32,
+    server_addr: Option<SocketAddr>,
+    options: &Options,
+) -> Result<(Verification, Option<Sizes>), Box<dyn Error>> {
+    let Options {
+        backend,
+        threads,
+        compress,
+        trace,
+        ..
+    } = *options;
+    let rule = client.rule();
+
+    let verification = match (server_addr, backend) {
+        (Some(addr), _) => {
+            let (verification, sizes) = net::run(client, backend, compress, addr, steps)?;
+            return Ok((verification, Some(sizes)));
+        }
+        (None, _) if compress => {
+            let (verification, sizes) = deploy::run(client, backend, true, steps, threads)?;
+            return Ok((verification, Some(sizes)));
+        }
+        (None, _) if options.contributors > 0 => {
+            let (verification, sizes) =
+                deploy::run_public(client, backend, options.contributors, steps, threads)?;
+            return Ok((verification, Some(sizes)));
+        }
+        (None, Backend::Integer) => evaluate(client, steps, trace, |server_key, grid| {
+            server::Server::new(server_key, grid, rule, threads)
+        }),
+        (None, Backend::Boolean) => evaluate(client, steps, trace, |server_key, grid| {
+            bool_server::BoolServer::new(server_key, grid, rule, threads)
+        }),
+        (None, Backend::Shortint) if options.circuit => {
+            evaluate(client, steps, trace, |server_key, grid| {
+                bool_server::BoolServer::shortint(server_key, grid, rule, threads)
+            })
+        }
+        (None, Backend::Shortint) if options.many_lut => {
+            evaluate(client, steps, trace, |server_key, grid| {
+                many_lut_server::ManyLutServer::new(server_key, grid, rule, threads)
+            })
+        }
+        (None, Backend::Shortint) => evaluate(client, steps, trace, |server_key, grid| {
+            shortint_server::ShortintServer::new(server_key, grid, rule, threads)
+        }),
+    };
+    Ok((verification, None))
+}
+
+/// Encrypt the client's grid, evolve it in-process on the server `new_server`
+/// creates and verify it, every generation with `trace`
+fn evaluate<C, S>(
+    client: &client::Client,
+    steps: u32,
+    trace: bool,
+    new_server: impl FnOnce(C::ServerKey, client::EncryptedGrid<C>) -> S,
+) -> Verification
+where
+    C: client::EncryptedCell,
+    S: Evolve<Cell = C>,
+    client::Client: AsRef<C::ClientKey>,
+{
+    let (server_key, encrypted_grid) = client.encrypt();
+    let server = client.timed(Phase::Evaluate, || new_server(server_key, encrypted_grid));
+    if trace {
+        let generations = client.timed(Phase::Evaluate, || server.generations(steps));
+        client.record_generations(server.generation_times(), server.traffic());
+        client.record_idle(server.idle());
+        client.verify_generations(generations)
+    } else {
+        let grid = client.timed(Phase::Evaluate, || server.run(steps));
+        client.record_generations(server.generation_times(), server.traffic());
+        client.record_idle(server.idle());
+        client.verify(grid, steps)
+    }
+}
@@ -0,0 +1,279 @@
+use crate::cell_server::{CellServer, CellUpdate};
+use crate::client::EncryptedGrid;
+use crate::grid::Grid;
+use crate::rule::Rule;
+use crate::server::plain_thread_pool;
+use tfhe::core_crypto::prelude::*;
+use tfhe::shortint::atomic_pattern::{AtomicPatternServerKey, StandardAtomicPatternServerKey};
+use tfhe::shortint::ciphertext::Degree;
+use tfhe::shortint::server_key::{
+    LookupTableOwned, ModulusSwitchNoiseReductionKey, ShortintBootstrappingKey,
+};
+use tfhe::shortint::{Ciphertext, PBSOrder, ServerKey};
+
+/// A count holds at most 8 neighbors
+const MAX_COUNT: u64 = 8;
+
+/// Functions of the count every blind rotation yields
+const TABLES: usize = 2;
+
+/// Server that gets two functions of the neighbor count out of a single
+/// blind rotation, then combines them with the cell in a second bootstrap
+///
+/// The many-LUT bootstrap is built on `core_crypto` with the keys of the
+/// client's shortint server key: the count is key switched and blind rotated
+/// once over an accumulator that holds both tables, each in its own slice of
+/// the polynomial, and one sample is extracted per table. The input has to
+/// fit a slice, so two tables leave half of the 5 bits of
+/// `SHORTINT_PARAMETERS` to the count, which is enough for 0 to 8 but not for
+/// the cell along with it.
+///
+/// The tables are `born` and `survives`, i.e. `count == 3` and
+/// `count == 2 || count == 3` for B3/S23. They are packed with the cell as
+/// `born + 2 * survives + 4 * alive`, at most 7, and the second bootstrap
+/// picks `survives` for a live cell and `born` for a dead one. Every cell
+/// takes two bootstraps, where `Server` bootstraps once per comparison of the
+/// count.
+pub(crate) type ManyLutServer = CellServer<ManyLutUpdate>;
+
+/// The cell update of a `ManyLutServer`
+pub(crate) struct ManyLutUpdate {
+    server_key: ServerKey,
+    count_tables: ManyLut,
+    combine_table: LookupTableOwned,
+}
+
+/// Lookup tables packed into one accumulator, one after the other
+struct ManyLut {
+    accumulator: GlweCiphertextOwned<u64>,
+    /// Coefficients from the start of one table to the next
+    stride: usize,
+    /// The largest input every table takes
+    max_input: u64,
+    /// The largest output of every table
+    degrees: [Degree; TABLES],
+}
+
+/// The keys a keyswitch-then-bootstrap shortint server key is made of
+struct BootstrapKeys<'k> {
+    key_switching_key: &'k LweKeyswitchKeyOwned<u64>,
+    bootstrap_key: &'k FourierLweBootstrapKeyOwned,
+    noise_reduction_key: Option<&'k ModulusSwitchNoiseReductionKey<u64>>,
+}
+
+impl ManyLutServer {
+    /// Create a server evaluating on `threads` worker threads,
+    /// or on every available core if `threads` is 0
+    pub(crate) fn new(
+        server_key: ServerKey,
+        grid: EncryptedGrid<Ciphertext>,
+        rule: Rule,
+        threads: usize,
+    ) -> Self {
+        let count_tables = ManyLut::new(
+            &server_key,
+            [&|count| born(rule, count), &|count| survives(rule, count)],
+        );
+        assert!(
+            MAX_COUNT <= count_tables.max_input,
+            "the tables only take counts up to {}",
+            count_tables.max_input
+        );
+        let combine_table = server_key.generate_lookup_table(combine);
+        let update = ManyLutUpdate {
+            server_key,
+            count_tables,
+            combine_table,
+        };
+        CellServer::with_update(update, plain_thread_pool(threads), grid)
+    }
+}
+
+impl CellUpdate for ManyLutUpdate {
+    type Cell = Ciphertext;
+
+    fn update_cell(&self, x: usize, y: usize, grid: &Grid<Ciphertext>) -> Ciphertext {
+        let mut count = self.server_key.create_trivial(0);
+        for neighbor in grid.neighbors(x, y) {
+            self.server_key.unchecked_add_assign(&mut count, neighbor);
+        }
+
+        let [born, survives] = self.count_tables.apply(&self.server_key, &count);
+
+        // Fresh out of bootstraps, the packing has a noise 2-norm of
+        // `sqrt(1 + 2^2 + 4^2) < 5`
+        let mut packed = grid[(x, y)].clone();
+        self.server_key.unchecked_scalar_mul_assign(&mut packed, 2);
+        self.server_key.unchecked_add_assign(&mut packed, &survives);
+        self.server_key.unchecked_scalar_mul_assign(&mut packed, 2);
+        self.server_key.unchecked_add_assign(&mut packed, &born);
+
+        self.server_key
+            .apply_lookup_table(&packed, &self.combine_table)
+    }
+}
+
+impl ManyLut {
+    /// Pack `tables` into an accumulator for the bootstrap key of
+    /// `server_key`
+    ///
+    /// Every table gets an equal share of the plaintext space, the inputs
+    /// of a ciphertext with padding bit. Within a table every input takes a
+    /// box of coefficients, centered on it so that noise in either direction
+    /// reads the same output.
+    fn new(server_key: &ServerKey, tables: [&dyn Fn(u64) -> u64; TABLES]) -> Self {
+        let keys = BootstrapKeys::of(server_key);
+        let polynomial_size = keys.bootstrap_key.polynomial_size();
+        let space = server_key.message_modulus.0 * server_key.carry_modulus.0;
+        let delta = (1 << 63) / space;
+        let box_size = polynomial_size.0 / space as usize;
+        let inputs = space / TABLES as u64;
+
+        let mut accumulator = GlweCiphertext::new(
+            0,
+            keys.bootstrap_key.glwe_size(),
+            polynomial_size,
+            server_key.ciphertext_modulus,
+        );
+        let mut body = accumulator.get_mut_body();
+        let body = body.as_mut();
+        for (coefficient, value) in body.iter_mut().enumerate() {
+            let input = (coefficient / box_size) as u64;
+            *value = tables[(input / inputs) as usize](input % inputs) * delta;
+        }
+        // The blind rotation is negacyclic, what wraps around is negated
+        let half_box = box_size / 2;
+        for value in &mut body[..half_box] {
+            *value = value.wrapping_neg();
+        }
+        body.rotate_left(half_box);
+
+        ManyLut {
+            accumulator,
+            stride: inputs as usize * box_size,
+            max_input: inputs - 1,
+            degrees: tables.map(|table| Degree::new((0..inputs).map(table).max().unwrap_or(0))),
+        }
+    }
+
+    /// Every table's output for `input`, from a single blind rotation
+    fn apply(&self, server_key: &ServerKey, input: &Ciphertext) -> [Ciphertext; TABLES] {
+        assert!(
+            input.degree.get() <= self.max_input,
+            "an input of degree {} does not fit tables of inputs up to {}",
+            input.degree.get(),
+            self.max_input
+        );
+        let keys = BootstrapKeys::of(server_key);
+
+        let mut switched = LweCiphertext::new(
+            0,
+            keys.key_switching_key.output_lwe_size(),
+            keys.key_switching_key.ciphertext_modulus(),
+        );
+        keyswitch_lwe_ciphertext(keys.key_switching_key, &input.ct, &mut switched);
+        if let Some(noise_reduction_key) = keys.noise_reduction_key {
+            let log_modulus = keys
+                .bootstrap_key
+                .polynomial_size()
+                .to_blind_rotation_input_modulus_log();
+            noise_reduction_key.improve_modulus_switch_noise(&mut switched, log_modulus);
+        }
+        let mut accumulator = self.accumulator.clone();
+        blind_rotate_assign(&switched, &mut accumulator, keys.bootstrap_key);
+
+        std::array::from_fn(|table| {
+            let mut output = input.clone();
+            extract_lwe_sample_from_glwe_ciphertext(
+                &accumulator,
+                &mut output.ct,
+                MonomialDegree(table * self.stride),
+            );
+            output.degree = self.degrees[table];
+            output.set_noise_level_to_nominal();
+            output
+        })
+    }
+}
+
+impl<'k> BootstrapKeys<'k> {
+    /// The keys of `server_key`, which has to be made for classic
+    /// keyswitch-then-bootstrap parameters as `SHORTINT_PARAMETERS` are
+    fn of(server_key: &'k ServerKey) -> Self {
+        match &server_key.atomic_pattern {
+            AtomicPatternServerKey::Standard(StandardAtomicPatternServerKey {
+                key_switching_key,
+                bootstrapping_key:
+                    ShortintBootstrappingKey::Classic {
+                        bsk,
+                        modulus_switch_noise_reduction_key,
+                    },
+                pbs_order: PBSOrder::KeyswitchBootstrap,
+            }) => BootstrapKeys {
+                key_switching_key,
+                bootstrap_key: bsk,
+                noise_reduction_key: modulus_switch_noise_reduction_key.as_ref(),
+            },
+            _ => panic!("the many-LUT server needs a classic keyswitch-then-bootstrap key"),
+        }
+    }
+}
+
+fn born(rule: Rule, count: u64) -> u64 {
+    rule.born(count as usize) as u64
+}
+
+fn survives(rule: Rule, count: u64) -> u64 {
+    rule.survives(count as usize) as u64
+}
+
+/// The next state from `born + 2 * survives + 4 * alive`
+fn combine(packed: u64) -> u64 {
+    let (born, survives, alive) = (packed & 1, packed >> 1 & 1, packed >> 2 & 1);
+    if alive == 1 { survives } else { born }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::Boundary;
+    use crate::client::{self, Client, Reference};
+    use crate::keystore;
+    use crate::server::Evolve;
+
+    /// The tables and how they are combined, against `Rule::next` for
+    /// sampled Life-like rules on every 3x3 neighborhood
+    #[test]
+    fn tables_follow_sampled_rules() {
+        for rule in Rule::sample(64) {
+            for neighborhood in 0..512u32 {
+                // The cell is the center bit, its neighbors the other eight
+                let alive = (neighborhood >> 4 & 1) as u64;
+                let count = (neighborhood & !(1 << 4)).count_ones() as u64;
+                let packed = born(rule, count) + 2 * survives(rule, count) + 4 * alive;
+                assert_eq!(
+                    combine(packed),
+                    rule.next(alive as u8, count as u8) as u64,
+                    "{rule} on neighborhood {neighborhood:#011b}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn server_follows_next_generation() {
+        // B3/S23, and a rule whose B is not in S
+        for (seed, rule) in [(1, "B3/S23"), (2, "B36/S125")] {
+            let rule: Rule = rule.parse().unwrap();
+            let grid = client::random_grid(3, 3, seed);
+            let mut client =
+                Client::with_keys(grid, rule, Boundary::Torus, keystore::shortint_test_keys());
+            client.set_reference(Reference::CellByCell);
+
+            let (server_key, grid) = client.encrypt::<Ciphertext>();
+            let server = ManyLutServer::new(server_key, grid, rule, 1);
+            let verification = client.verify(server.run(1), 1);
+            assert!(verification.passed(), "{rule}: {verification}");
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use crate::backend::Backend;
+use crate::client::Client;
+use crate::deploy::{self, Sizes};
+use crate::format::Kind;
+use crate::phases::Phase;
+use crate::verification::Verification;
+use std::error::Error;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// Bumped whenever the sequence or layout of frames changes
+pub(crate) const PROTOCOL_VERSION: u32 = 1;
+
+/// Largest frame either side accepts, server keys are a few hundred MB
+const MAX_FRAME_LEN: u64 = 1 << 30;
+
+/// One message of the protocol
+///
+/// On the wire a frame is its tag byte, the length of its body as a
+/// little-endian `u64`, and the body. A session goes
+///
+/// ```text
+/// client                          server
+///   Hello(version)        ->
+///                         <-      Hello(version) or Error
+///   ServerKey, Grid, Steps ->
+///                         <-      Result or Error
+/// ```
+///
+/// where the server key, grid and result are in the file format.
+#[derive(Debug)]
+pub(crate) enum Frame {
+    Hello(u32),
+    ServerKey(Vec<u8>),
+    Grid(Vec<u8>),
+    Steps(u32),
+    Result(Vec<u8>),
+    Error(String),
+}
+
+impl Frame {
+    fn tag(&self) -> u8 {
+        match self {
+            Frame::Hello(_) => 0,
+            Frame::ServerKey(_) => 1,
+            Frame::Grid(_) => 2,
+            Frame::Steps(_) => 3,
+            Frame::Result(_) => 4,
+            Frame::Error(_) => 5,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Frame::Hello(_) => "Hello",
+            Frame::ServerKey(_) => "ServerKey",
+            Frame::Grid(_) => "Grid",
+            Frame::Steps(_) => "Steps",
+            Frame::Result(_) => "Result",
+            Frame::Error(_) => "Error",
+        }
+    }
+
+    pub(crate) fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let body = match self {
+            Frame::Hello(n) | Frame::Steps(n) => &n.to_le_bytes()[..],
+            Frame::ServerKey(bytes) | Frame::Grid(bytes) | Frame::Result(bytes) => bytes,
+            Frame::Error(message) => message.as_bytes(),
+        };
+
+        writer.write_all(&[self.tag()])?;
+        writer.write_all(&(body.len() as u64).to_le_bytes())?;
+        writer.write_all(body)?;
+        writer.flush()
+    }
+
+    pub(crate) fn read(mut reader: impl Read) -> Result<Self, Box<dyn Error>> {
+        let mut tag = [0; 1];
+        reader.read_exact(&mut tag)?;
+        let mut len = [0; 8];
+        reader.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len);
+        if len > MAX_FRAME_LEN {
+            return Err(format!("frame of {len} bytes is larger than {MAX_FRAME_LEN}").into());
+        }
+
+        // Grow the body as the bytes arrive instead of trusting the length
+        let mut body = vec![];
+        (&mut reader).take(len).read_to_end(&mut body)?;
+        if body.len() as u64 != len {
+            return Err(format!("frame ended after {} of its {len} bytes", body.len()).into());
+        }
+        let number = |body: Vec<u8>| -> Result<u32, Box<dyn Error>> {
+            let bytes = body.try_into().map_err(|_| "malformed frame")?;
+            Ok(u32::from_le_bytes(bytes))
+        };
+
+        Ok(match tag[0] {
+            0 => Frame::Hello(number(body)?),
+            1 => Frame::ServerKey(body),
+            2 => Frame::Grid(body),
+            3 => Frame::Steps(number(body)?),
+            4 => Frame::Result(body),
+            5 => Frame::Error(String::from_utf8_lossy(&body).into_owned()),
+            tag => return Err(format!("unknown frame tag {tag}").into()),
+        })
+    }
+}
+
+/// Serve clients one after the other until the listener fails
+///
+/// A failing session is reported to its client and does not stop the server.
+pub(crate) fn listen(listener: TcpListener, threads: usize) -> io::Result<()> {
+    loop {
+        let (stream, peer) = listener.accept()?;
+        if let Err(e) = handle(stream, threads) {
+            eprintln!("session with {peer} failed: {e}");
+        }
+    }
+}
+
+/// Run one session on the server side
+fn handle(stream: TcpStream, threads: usize) -> Result<(), Box<dyn Error>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    match session(&mut reader, &mut writer, threads) {
+        Ok(result) => Ok(Frame::Result(result).write(&mut writer)?),
+        Err(e) => {
+            // The client may already be gone, the original error matters more
+            let _ = Frame::Error(e.to_string()).write(&mut writer);
+            Err(e)
+        }
+    }
+}
+
+fn session(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    threads: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    match Frame::read(&mut reader)? {
+        Frame::Hello(PROTOCOL_VERSION) => Frame::Hello(PROTOCOL_VERSION).write(&mut writer)?,
+        Frame::Hello(version) => {
+            return Err(format!(
+                "protocol version {version} is not supported, expected {PROTOCOL_VERSION}"
+            )
+            .into());
+        }
+        frame => return Err(unexpected(&frame, "Hello")),
+    }
+
+    let key = match Frame::read(&mut reader)? {
+        Frame::ServerKey(key) => key,
+        frame => return Err(unexpected(&frame, "ServerKey")),
+    };
+    let grid = match Frame::read(&mut reader)? {
+        Frame::Grid(grid) => grid,
+        frame => return Err(unexpected(&frame, "Grid")),
+    };
+    let steps = match Frame::read(&mut reader)? {
+        Frame::Steps(steps) => steps,
+        frame => return Err(unexpected(&frame, "Steps")),
+    };
+
+    let mut result = vec![];
+    deploy::evolve(&key[..], &grid[..], steps, threads, &mut result)?;
+    Ok(result)
+}
+
+/// Run one session on the client side
+///
+/// # Returns
+/// The result grid in the file format.
+pub(crate) fn request(
+    addr: impl ToSocketAddrs,
+    key: Vec<u8>,
+    grid: Vec<u8>,
+    steps: u32,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let stream = TcpStream::connect(addr)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    Frame::Hello(PROTOCOL_VERSION).write(&mut writer)?;
+    match Frame::read(&mut reader)? {
+        Frame::Hello(PROTOCOL_VERSION) => {}
+        Frame::Hello(version) => {
+            return Err(format!(
+                "server speaks protocol version {version}, this client speaks {PROTOCOL_VERSION}"
+            )
+            .into());
+        }
+        Frame::Error(message) => return Err(format!("server error: {message}").into()),
+        frame => return Err(unexpected(&frame, "Hello")),
+    }
+
+    Frame::ServerKey(key).write(&mut writer)?;
+    Frame::Grid(grid).write(&mut writer)?;
+    Frame::Steps(steps).write(&mut writer)?;
+    match Frame::read(&mut reader)? {
+        Frame::Result(result) => Ok(result),
+        Frame::Error(message) => Err(format!("server error: {message}").into()),
+        frame => Err(unexpected(&frame, "Result")),
+    }
+}
+
+fn unexpected(frame: &Frame, expected: &str) -> Box<dyn Error> {
+    format!("expected a {expected} frame, got a {} frame", frame.name()).into()
+}
+
+/// Encrypt the client's grid, have the server at `addr` run `steps`
+/// generations on it and verify the result
+///
+/// # Returns
+/// The verification of the result and the size of every artifact.
+pub(crate) fn run(
+    client: &Client,
+    backend: Backend,
+    compress: bool,
+    addr: impl ToSocketAddrs,
+    steps: u32,
+) -> Result<(Verification, Sizes), Box<dyn Error>> {
+    let (mut key, mut grid) = (vec![], vec![]);
+    deploy::write_inputs(client, backend, compress, &mut key, &mut grid)?;
+    let (key_size, grid_size) = (key.len(), grid.len());
+
+    let result = client.timed(Phase::Evaluate, || request(addr, key, grid, steps))?;
+    let expected = client.header(Kind::Client, backend, 0);
+    let (_, verification) = deploy::check_result(client, &expected, Some(steps), &result[..])?;
+    let sizes = Sizes {
+        key: key_size,
+        grid: grid_size,
+        result: result.len(),
+    };
+    Ok((verification, sizes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::Boundary;
+    use crate::client::EncryptedGrid;
+    use crate::format::{self, Header};
+    use crate::keystore;
+    use crate::rule::Rule;
+    use std::net::SocketAddr;
+    use tfhe::shortint::Ciphertext;
+
+    /// A server on a free port of localhost, for as long as the tests run
+    fn serve() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || listen(listener, 1));
+        addr
+    }
+
+    /// Say hello with `version`, send `frames` once the server answers and
+    /// return its last frame
+    fn exchange(addr: SocketAddr, version: u32, frames: &[Frame]) -> Frame {
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = BufWriter::new(stream);
+        Frame::Hello(version).write(&mut writer).unwrap();
+        match Frame::read(&mut reader).unwrap() {
+            Frame::Hello(_) => {}
+            frame => return frame,
+        }
+        for frame in frames {
+            frame.write(&mut writer).unwrap();
+        }
+        Frame::read(&mut reader).unwrap()
+    }
+
+    fn error_message(frame: Frame) -> String {
+        match frame {
+            Frame::Error(message) => message,
+            frame => panic!("expected an Error frame, got a {} frame", frame.name()),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let addr = serve();
+        let client = blinker_client();
+
+        let (verification, sizes) = run(&client, Backend::Shortint, false, addr, 1).unwrap();
+        assert!(verification.passed(), "{verification}");
+        assert!(sizes.key > 0 && sizes.grid > 0 && sizes.result > 0);
+    }
+
+    /// A server on a free port of localhost that answers one session with
+    /// the grid it was sent, claiming to have run no steps on it
+    fn serve_untouched() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = BufWriter::new(stream);
+            Frame::read(&mut reader).unwrap();
+            Frame::Hello(PROTOCOL_VERSION).write(&mut writer).unwrap();
+            let Frame::ServerKey(_) = Frame::read(&mut reader).unwrap() else {
+                panic!("expected a ServerKey frame");
+            };
+            let Frame::Grid(grid) = Frame::read(&mut reader).unwrap() else {
+                panic!("expected a Grid frame");
+            };
+            Frame::read(&mut reader).unwrap();
+
+            let mut grid = &grid[..];
+            let header = format::read_header(&mut grid, Kind::Grid).unwrap();
+            let cells: EncryptedGrid<Ciphertext> = format::read_payload(grid).unwrap();
+            let header = Header {
+                kind: Kind::Result,
+                steps: 0,
+                ..header
+            };
+            let mut result = vec![];
+            format::write_to(&mut result, &header, &cells).unwrap();
+            Frame::Result(result).write(&mut writer).unwrap();
+        });
+        addr
+    }
+
+    fn blinker_client() -> Client {
+        // A blinker, which turns from a column into a row
+        let grid = vec![vec![0, 1, 0], vec![0, 1, 0], vec![0, 1, 0]];
+        let keys = keystore::shortint_test_keys();
+        Client::with_keys(grid, Rule::default(), Boundary::Dead, keys)
+    }
+
+    #[test]
+    fn rejects_results_of_other_step_counts() {
+        let addr = serve_untouched();
+        let client = blinker_client();
+
+        // The blinker is back after 2 steps, so only the step count gives
+        // the untouched grid away
+        let result = run(&client, Backend::Shortint, false, addr, 2);
+        let Err(error) = result else {
+            panic!("the result has to be rejected");
+        };
+        let message = error.to_string();
+        assert!(message.contains("asked for 2"), "{message}");
+    }
+
+    #[test]
+    fn rejects_other_protocol_versions() {
+        let addr = serve();
+        let message = error_message(exchange(addr, PROTOCOL_VERSION + 1, &[]));
+        assert!(message.contains("not supported"), "{message}");
+    }
+
+    #[test]
+    fn rejects_empty_grids_and_keeps_serving() {
+        let addr = serve();
+        let header = |kind| Header {
+            kind,
+            backend: Backend::Integer,
+            m: 0,
+            n: 0,
+            rule: Rule::default(),
+            boundary: Boundary::Dead,
+            steps: 0,
+            compressed: false,
+        };
+        let (mut key, mut grid) = (vec![], vec![]);
+        format::write_to(&mut key, &header(Kind::ServerKey), &()).unwrap();
+        format::write_to(&mut grid, &header(Kind::Grid), &()).unwrap();
+
+        // The first session fails without taking the server down with it
+        for _ in 0..2 {
+            let frames = [
+                Frame::ServerKey(key.clone()),
+                Frame::Grid(grid.clone()),
+                Frame::Steps(1),
+            ];
+            let message = error_message(exchange(addr, PROTOCOL_VERSION, &frames));
+            assert!(message.contains("empty"), "{message}");
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Add;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tfhe::prelude::*;
+use tfhe::{FheBool, FheUint8};
+
+/// The homomorphic operations the integer server's circuit is built from
+///
+/// A cell is an 8-bit counter and a predicate is a single bit. Real
+/// evaluation is `Tfhe`, `Mock` runs the same circuit on cleartext and counts
+/// every operation instead.
+pub(crate) trait Ops: Sync {
+    type Cell: Clone + Send + Sync;
+    type Bool: Clone + Send + Sync;
+
+    /// A trivially encrypted cell, free to make
+    fn constant(&self, value: u8) -> Self::Cell;
+    /// A trivially encrypted predicate, free to make
+    fn constant_bool(&self, value: bool) -> Self::Bool;
+    fn add(&self, a: &Self::Cell, b: &Self::Cell) -> Self::Cell;
+    fn sub(&self, a: &Self::Cell, b: &Self::Cell) -> Self::Cell;
+    fn add_assign(&self, acc: &mut Self::Cell, other: &Self::Cell);
+    fn sub_assign(&self, acc: &mut Self::Cell, other: &Self::Cell);
+    fn eq(&self, cell: &Self::Cell, value: u8) -> Self::Bool;
+    fn or(&self, a: &Self::Bool, b: &Self::Bool) -> Self::Bool;
+    fn select(
+        &self,
+        condition: &Self::Bool,
+        then: &Self::Cell,
+        otherwise: &Self::Cell,
+    ) -> Self::Cell;
+}
+
+/// Real evaluation on `FheUint8` cells
+///
+/// The server key has to be installed on every thread evaluating cells,
+/// see `server::thread_pool`.
+pub(crate) struct Tfhe;
+
+impl Ops for Tfhe {
+    type Cell = FheUint8;
+    type Bool = FheBool;
+
+    fn constant(&self, value: u8) -> FheUint8 {
+        FheUint8::try_encrypt_trivial(value).unwrap()
+    }
+
+    fn constant_bool(&self, value: bool) -> FheBool {
+        FheBool::encrypt_trivial(value)
+    }
+
+    fn add(&self, a: &FheUint8, b: &FheUint8) -> FheUint8 {
+        a + b
+    }
+
+    fn sub(&self, a: &FheUint8, b: &FheUint8) -> FheUint8 {
+        a - b
+    }
+
+    fn add_assign(&self, acc: &mut FheUint8, other: &FheUint8) {
+        *acc += other;
+    }
+
+    fn sub_assign(&self, acc: &mut FheUint8, other: &FheUint8) {
+        *acc -= other;
+    }
+
+    fn eq(&self, cell: &FheUint8, value: u8) -> FheBool {
+        cell.eq(value)
+    }
+
+    fn or(&self, a: &FheBool, b: &FheBool) -> FheBool {
+        a | b
+    }
+
+    fn select(&self, condition: &FheBool, then: &FheUint8, otherwise: &FheUint8) -> FheUint8 {
+        condition.select(then, otherwise)
+    }
+}
+
+/// How many times each kind of operation was evaluated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct OpCounts {
+    /// Additions and subtractions, which cost the same
+    pub(crate) additions: u64,
+    pub(crate) comparisons: u64,
+    pub(crate) selects: u64,
+    /// Operations on predicates, each a single bootstrap
+    pub(crate) bootstraps: u64,
+}
+
+impl Add for OpCounts {
+    type Output = OpCounts;
+
+    fn add(self, other: OpCounts) -> OpCounts {
+        OpCounts {
+            additions: self.additions + other.additions,
+            comparisons: self.comparisons + other.comparisons,
+            selects: self.selects + other.selects,
+            bootstraps: self.bootstraps + other.bootstraps,
+        }
+    }
+}
+
+/// Cleartext evaluation that counts operations
+///
+/// Cells are plain `u8`s wrapping around like `FheUint8` does, so a grid
+/// evolved by the mock is exactly what the real server would decrypt to.
+#[derive(Debug, Default)]
+pub(crate) struct Mock {
+    additions: AtomicU64,
+    comparisons: AtomicU64,
+    selects: AtomicU64,
+    bootstraps: AtomicU64,
+}
+
+impl Mock {
+    /// Operations evaluated so far
+    pub(crate) fn counts(&self) -> OpCounts {
+        OpCounts {
+            additions: self.additions.load(Ordering::Relaxed),
+            comparisons: self.comparisons.load(Ordering::Relaxed),
+            selects: self.selects.load(Ordering::Relaxed),
+            bootstraps: self.bootstraps.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Ops for Mock {
+    type Cell = u8;
+    type Bool = bool;
+
+    fn constant(&self, value: u8) -> u8 {
+        value
+    }
+
+    fn constant_bool(&self, value: bool) -> bool {
+        value
+    }
+
+    fn add(&self, a: &u8, b: &u8) -> u8 {
+        self.additions.fetch_add(1, Ordering::Relaxed);
+        a.wrapping_add(*b)
+    }
+
+    fn sub(&self, a: &u8, b: &u8) -> u8 {
+        self.additions.fetch_add(1, Ordering::Relaxed);
+        a.wrapping_sub(*b)
+    }
+
+    fn add_assign(&self, acc: &mut u8, other: &u8) {
+        self.additions.fetch_add(1, Ordering::Relaxed);
+        *acc = acc.wrapping_add(*other);
+    }
+
+    fn sub_assign(&self, acc: &mut u8, other: &u8) {
+        self.additions.fetch_add(1, Ordering::Relaxed);
+        *acc = acc.wrapping_sub(*other);
+    }
+
+    fn eq(&self, cell: &u8, value: u8) -> bool {
+        self.comparisons.fetch_add(1, Ordering::Relaxed);
+        *cell == value
+    }
+
+    fn or(&self, a: &bool, b: &bool) -> bool {
+        self.bootstraps.fetch_add(1, Ordering::Relaxed);
+        *a || *b
+    }
+
+    fn select(&self, condition: &bool, then: &u8, otherwise: &u8) -> u8 {
+        self.selects.fetch_add(1, Ordering::Relaxed);
+        if *condition { *then } else { *otherwise }
+    }
+}
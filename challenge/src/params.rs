@@ -0,0 +1,277 @@
+use crate::keystore::Keys;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use tfhe::core_crypto::prelude::DynamicDistribution;
+use tfhe::prelude::*;
+use tfhe::shortint::parameters::PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M128;
+use tfhe::shortint::parameters::v1_2::*;
+use tfhe::shortint::{ClassicPBSParameters, MultiBitPBSParameters};
+use tfhe::{ConfigBuilder, FheBool, FheUint8};
+
+/// Security tfhe targets with every parameter set it ships
+///
+/// This is the nominal level the sets were chosen for, not an estimate of
+/// any set. Their structs carry no security of their own to report.
+const TARGET_SECURITY_BITS: u32 = 128;
+
+/// Bits of message and carry tfhe has secure parameters for, a larger
+/// plaintext space needs larger polynomials than it offers
+const MAX_PLAINTEXT_BITS: u8 = 8;
+
+/// The TFHE parameters the integer and boolean keys are generated with
+///
+/// The shortint backend always uses `SHORTINT_PARAMETERS`, whose lookup
+/// table needs exactly that plaintext space.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum ParameterSet {
+    /// tfhe's default, classic PBS on 2 message and 2 carry bits
+    ///
+    /// It draws TUniform noise, where `m2c2` draws Gaussian noise like the
+    /// other sets: tfhe ships TUniform sets for few of the custom sizes.
+    #[default]
+    Default,
+    /// Multi-bit PBS on 2 message and 2 carry bits, which blind rotates
+    /// `group` bits of the mask at once, or as many as suit the cores if
+    /// `None`, see `resolve`
+    MultiBit { group: Option<u8> },
+    /// Classic PBS on `message` message and `carry` carry bits
+    Custom { message: u8, carry: u8 },
+}
+
+/// The block parameters of a set, of either kind of PBS
+#[derive(Clone, Copy, Debug)]
+enum Blocks {
+    Classic(ClassicPBSParameters),
+    MultiBit(MultiBitPBSParameters),
+}
+
+impl ParameterSet {
+    /// Multi-bit parameters with the group that suits `threads` worker
+    /// threads
+    ///
+    /// A group of `g` bits takes `2^g - 1` products with the bootstrapping
+    /// key per step of the blind rotation, but `g` times fewer steps. tfhe
+    /// spreads the products of a step over threads, so larger groups only
+    /// pay off with more cores to run them on.
+    pub(crate) fn multi_bit(threads: usize) -> Self {
+        let group = match threads {
+            0..=3 => 2,
+            4..=15 => 3,
+            _ => 4,
+        };
+        ParameterSet::MultiBit { group: Some(group) }
+    }
+
+    /// This set with the multi-bit group chosen for `threads` if it was
+    /// left open
+    pub(crate) fn resolve(self, threads: usize) -> Self {
+        match self {
+            ParameterSet::MultiBit { group: None } => Self::multi_bit(threads),
+            set => set,
+        }
+    }
+
+    /// A config for keys of this set, or an error if the set cannot work
+    pub(crate) fn config(self) -> Result<ConfigBuilder, Box<dyn Error>> {
+        Ok(match self.blocks()? {
+            Blocks::Classic(blocks) => ConfigBuilder::with_custom_parameters(blocks),
+            Blocks::MultiBit(blocks) => ConfigBuilder::with_custom_parameters(blocks),
+        })
+    }
+
+    fn blocks(self) -> Result<Blocks, String> {
+        match self {
+            ParameterSet::Default => Ok(Blocks::Classic(
+                PARAM_MESSAGE_2_CARRY_2_KS_PBS_TUNIFORM_2M128,
+            )),
+            ParameterSet::MultiBit { group: None } => {
+                self.resolve(rayon::current_num_threads()).blocks()
+            }
+            ParameterSet::MultiBit { group: Some(group) } => match group {
+                2 => Ok(V1_2_PARAM_MULTI_BIT_GROUP_2_MESSAGE_2_CARRY_2_KS_PBS_GAUSSIAN_2M128),
+                3 => Ok(V1_2_PARAM_MULTI_BIT_GROUP_3_MESSAGE_2_CARRY_2_KS_PBS_GAUSSIAN_2M128),
+                4 => Ok(V1_2_PARAM_MULTI_BIT_GROUP_4_MESSAGE_2_CARRY_2_KS_PBS_GAUSSIAN_2M128),
+                _ => Err(format!(
+                    "multi-bit PBS groups 2, 3 or 4 bits of the mask, not {group}"
+                )),
+            }
+            .map(Blocks::MultiBit),
+            ParameterSet::Custom { message, carry } => {
+                custom_blocks(message, carry).map(Blocks::Classic)
+            }
+        }
+    }
+}
+
+/// The classic parameters for `message` and `carry` bits
+///
+/// `FheUint8` splits its 8 bits into blocks of `message` bits, and adding
+/// or comparing two blocks needs a carry space at least as large as their
+/// message space.
+fn custom_blocks(message: u8, carry: u8) -> Result<ClassicPBSParameters, String> {
+    if message == 0 || 8 % message != 0 {
+        return Err(format!(
+            "{message} message bits do not divide the 8 bits of a cell, use 1, 2 or 4"
+        ));
+    }
+    if carry < message {
+        return Err(format!(
+            "{carry} carry bits cannot hold the carries of {message} message bits, \
+             use at least {message}"
+        ));
+    }
+    if message + carry > MAX_PLAINTEXT_BITS {
+        return Err(format!(
+            "there are no parameters targeting {TARGET_SECURITY_BITS}-bit security for {} bits \
+             of message and carry, use at most {MAX_PLAINTEXT_BITS}",
+            message + carry
+        ));
+    }
+
+    Ok(match (message, carry) {
+        (1, 1) => V1_2_PARAM_MESSAGE_1_CARRY_1_KS_PBS_GAUSSIAN_2M128,
+        (1, 2) => V1_2_PARAM_MESSAGE_1_CARRY_2_KS_PBS_GAUSSIAN_2M128,
+        (1, 3) => V1_2_PARAM_MESSAGE_1_CARRY_3_KS_PBS_GAUSSIAN_2M128,
+        (1, 4) => V1_2_PARAM_MESSAGE_1_CARRY_4_KS_PBS_GAUSSIAN_2M128,
+        (1, 5) => V1_2_PARAM_MESSAGE_1_CARRY_5_KS_PBS_GAUSSIAN_2M128,
+        (1, 6) => V1_2_PARAM_MESSAGE_1_CARRY_6_KS_PBS_GAUSSIAN_2M128,
+        (1, 7) => V1_2_PARAM_MESSAGE_1_CARRY_7_KS_PBS_GAUSSIAN_2M128,
+        (2, 2) => V1_2_PARAM_MESSAGE_2_CARRY_2_KS_PBS_GAUSSIAN_2M128,
+        (2, 3) => V1_2_PARAM_MESSAGE_2_CARRY_3_KS_PBS_GAUSSIAN_2M128,
+        (2, 4) => V1_2_PARAM_MESSAGE_2_CARRY_4_KS_PBS_GAUSSIAN_2M128,
+        (2, 5) => V1_2_PARAM_MESSAGE_2_CARRY_5_KS_PBS_GAUSSIAN_2M128,
+        (2, 6) => V1_2_PARAM_MESSAGE_2_CARRY_6_KS_PBS_GAUSSIAN_2M128,
+        (4, 4) => V1_2_PARAM_MESSAGE_4_CARRY_4_KS_PBS_GAUSSIAN_2M128,
+        _ => unreachable!("every valid combination is listed"),
+    })
+}
+
+impl FromStr for ParameterSet {
+    type Err = String;
+
+    /// `default`, `multi-bit` (grouping as many bits as suit the cores),
+    /// `multi-bit:G`, or `mMcC` for `M` message and `C` carry bits
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "unknown parameter set `{s}`, expected `default`, `multi-bit`, `multi-bit:G` \
+                 or `mMcC`, e.g. `m2c3`"
+            )
+        };
+        let set = match s {
+            "default" => ParameterSet::Default,
+            "multi-bit" => ParameterSet::MultiBit { group: None },
+            _ => {
+                if let Some(group) = s.strip_prefix("multi-bit:") {
+                    let group = group.parse().map_err(|_| invalid())?;
+                    ParameterSet::MultiBit { group: Some(group) }
+                } else {
+                    let (message, carry) = s
+                        .strip_prefix('m')
+                        .and_then(|rest| rest.split_once('c'))
+                        .ok_or_else(invalid)?;
+                    ParameterSet::Custom {
+                        message: message.parse().map_err(|_| invalid())?,
+                        carry: carry.parse().map_err(|_| invalid())?,
+                    }
+                }
+            }
+        };
+        // Reject a set that cannot work as early as it is named
+        set.blocks()?;
+        Ok(set)
+    }
+}
+
+impl fmt::Display for ParameterSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParameterSet::Default => write!(f, "default"),
+            ParameterSet::MultiBit { group: None } => write!(f, "multi-bit"),
+            ParameterSet::MultiBit { group: Some(group) } => write!(f, "multi-bit:{group}"),
+            ParameterSet::Custom { message, carry } => write!(f, "m{message}c{carry}"),
+        }
+    }
+}
+
+/// What a parameter set claims and what its keys and ciphertexts take, to
+/// trade speed against security knowingly
+pub(crate) struct ParameterReport {
+    set: ParameterSet,
+    log2_p_fail: f64,
+    noise: &'static str,
+    lwe_dimension: usize,
+    polynomial_size: usize,
+    client_key: u64,
+    server_key: u64,
+    integer_cell: u64,
+    boolean_cell: u64,
+}
+
+impl ParameterReport {
+    /// Measure the serialized sizes on keys generated for `set`
+    pub(crate) fn new(set: ParameterSet, keys: &Keys) -> Result<Self, Box<dyn Error>> {
+        let Keys::Integer {
+            client_key,
+            server_key,
+            ..
+        } = keys
+        else {
+            return Err("parameter sets are for the integer and boolean keys".into());
+        };
+        let (log2_p_fail, noise, lwe_dimension, polynomial_size) = match set.blocks()? {
+            Blocks::Classic(blocks) => (
+                blocks.log2_p_fail,
+                blocks.lwe_noise_distribution,
+                blocks.lwe_dimension.0,
+                blocks.polynomial_size.0,
+            ),
+            Blocks::MultiBit(blocks) => (
+                blocks.log2_p_fail,
+                blocks.lwe_noise_distribution,
+                blocks.lwe_dimension.0,
+                blocks.polynomial_size.0,
+            ),
+        };
+        Ok(ParameterReport {
+            set,
+            log2_p_fail,
+            noise: match noise {
+                DynamicDistribution::Gaussian(_) => "Gaussian",
+                DynamicDistribution::TUniform(_) => "TUniform",
+            },
+            lwe_dimension,
+            polynomial_size,
+            client_key: size(client_key)?,
+            server_key: size(server_key)?,
+            integer_cell: size(&FheUint8::encrypt(0u8, client_key))?,
+            boolean_cell: size(&FheBool::encrypt(false, client_key))?,
+        })
+    }
+}
+
+impl fmt::Display for ParameterReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "parameters: {} (nominal {TARGET_SECURITY_BITS}-bit security target, PBS failure \
+             probability 2^{:.1}, {} noise, LWE dimension {}, polynomial size {}), client key \
+             {:.2} MB, server key {:.2} MB, FheUint8 {:.2} kB, FheBool {:.2} kB",
+            self.set,
+            self.log2_p_fail,
+            self.noise,
+            self.lwe_dimension,
+            self.polynomial_size,
+            self.client_key as f64 / 1e6,
+            self.server_key as f64 / 1e6,
+            self.integer_cell as f64 / 1e3,
+            self.boolean_cell as f64 / 1e3,
+        )
+    }
+}
+
+fn size<T: Serialize>(value: &T) -> Result<u64, Box<dyn Error>> {
+    Ok(bincode::serialized_size(value)?)
+}
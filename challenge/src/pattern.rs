@@ -0,0 +1,341 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A finite Life pattern, a rectangle of dead and live cells
+///
+/// Parsed from any of the common pattern formats, the format is told apart
+/// by the content of the file:
+/// * Life 1.06, a `#Life 1.06` line followed by one `x y` coordinate per live cell,
+/// * RLE, a `x = 3, y = 3` header followed by run-length encoded rows,
+/// * plaintext `.cells`, one row per line with `.` for dead and `O` for live cells.
+///
+/// Only the runs of live cells are kept, so a pattern takes no more memory
+/// than its file however large a size the file declares.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Pattern {
+    height: usize,
+    width: usize,
+    /// Runs of live cells as their row, first column and length
+    runs: Vec<(usize, usize, usize)>,
+}
+
+impl Pattern {
+    pub(crate) fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        Ok(text
+            .parse()
+            .map_err(|e| format!("failed to parse {}: {e}", path.display()))?)
+    }
+
+    pub(crate) fn height(&self) -> usize {
+        self.height
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    /// An `m * n` grid of dead cells with the pattern's top left corner at
+    /// `(row, col)`
+    pub(crate) fn place(
+        &self,
+        (m, n): (usize, usize),
+        (row, col): (usize, usize),
+    ) -> Result<Vec<Vec<u8>>, String> {
+        let fits =
+            |start: usize, length, size| start.checked_add(length).is_some_and(|end| end <= size);
+        if !fits(row, self.height, m) || !fits(col, self.width, n) {
+            return Err(format!(
+                "a {}x{} pattern at ({row}, {col}) does not fit in a {m}x{n} grid",
+                self.height(),
+                self.width()
+            ));
+        }
+
+        let mut grid = vec![vec![0; n]; m];
+        for &(i, j, length) in &self.runs {
+            grid[row + i][col + j..col + j + length].fill(1);
+        }
+        Ok(grid)
+    }
+
+    /// Build a pattern from the coordinates of its live cells
+    fn from_cells(cells: &[(i64, i64)]) -> Result<Self, String> {
+        let Some(top) = cells.iter().map(|&(i, _)| i).min() else {
+            return Ok(Pattern::from_rows(vec![]));
+        };
+        let left = cells.iter().map(|&(_, j)| j).min().unwrap();
+        let bottom = cells.iter().map(|&(i, _)| i).max().unwrap();
+        let right = cells.iter().map(|&(_, j)| j).max().unwrap();
+
+        // The distance of two i64 always fits a u64
+        let span = |low: i64, high: i64| high.abs_diff(low) as usize;
+        let (height, width) = (span(top, bottom), span(left, right));
+        if height == usize::MAX || width == usize::MAX {
+            return Err("Life 1.06 cells span more rows or columns than fit in memory".into());
+        }
+        let runs = cells
+            .iter()
+            .map(|&(i, j)| (span(top, i), span(left, j), 1))
+            .collect();
+        Ok(Pattern {
+            height: height + 1,
+            width: width + 1,
+            runs,
+        })
+    }
+
+    /// Build a pattern from rows of possibly different lengths
+    fn from_rows(rows: Vec<Vec<u8>>) -> Self {
+        let mut runs = vec![];
+        for (i, row) in rows.iter().enumerate() {
+            for (j, &cell) in row.iter().enumerate() {
+                match runs.last_mut() {
+                    _ if cell == 0 => {}
+                    Some((row, col, length)) if *row == i && *col + *length == j => *length += 1,
+                    _ => runs.push((i, j, 1)),
+                }
+            }
+        }
+        Pattern {
+            height: rows.len(),
+            width: rows.iter().map(Vec::len).max().unwrap_or(0),
+            runs,
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim_start().starts_with("#Life 1.06") {
+            parse_life_106(content_of(s.lines(), '#'))
+        } else if content_of(s.lines(), '#')
+            .next()
+            .is_some_and(|line| line.starts_with('x'))
+        {
+            parse_rle(content_of(s.lines(), '#'))
+        } else {
+            // Empty lines are rows of dead cells here, except at the end
+            let mut lines: Vec<&str> = s
+                .lines()
+                .map(str::trim_end)
+                .filter(|line| !line.starts_with('!'))
+                .collect();
+            while lines.last().is_some_and(|line| line.is_empty()) {
+                lines.pop();
+            }
+            parse_cells(lines.into_iter())
+        }
+    }
+}
+
+/// Non-empty lines that are not comments
+fn content_of<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    comment: char,
+) -> impl Iterator<Item = &'a str> {
+    lines
+        .map(str::trim)
+        .filter(move |line| !line.is_empty() && !line.starts_with(comment))
+}
+
+/// One `x y` line per live cell, `x` being the column and `y` the row
+fn parse_life_106<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Pattern, String> {
+    let mut cells = vec![];
+    for line in lines {
+        let coordinates = line
+            .split_whitespace()
+            .map(str::parse::<i64>)
+            .collect::<Result<Vec<_>, _>>();
+        match coordinates.as_deref() {
+            Ok(&[x, y]) => cells.push((y, x)),
+            _ => return Err(format!("`{line}` is not a Life 1.06 `x y` coordinate")),
+        }
+    }
+    Pattern::from_cells(&cells)
+}
+
+/// A `x = 3, y = 3[, rule = B3/S23]` header, then rows of `<count><tag>`
+/// runs where `b` is dead, any other letter is live, `$` ends a row and `!`
+/// ends the pattern
+fn parse_rle<'a>(mut lines: impl Iterator<Item = &'a str>) -> Result<Pattern, String> {
+    let header = lines.next().unwrap_or_default();
+    let mut size = (None, None);
+    for field in header.split(',') {
+        let (key, value) = field.split_once('=').unwrap_or((field, ""));
+        let value = value.trim().parse::<usize>();
+        match key.trim() {
+            "x" => size.1 = value.ok(),
+            "y" => size.0 = value.ok(),
+            _ => {}
+        }
+    }
+    let (Some(height), Some(width)) = size else {
+        return Err(format!("`{header}` is not an RLE `x = .., y = ..` header"));
+    };
+
+    // Nothing is allocated for the size the header declares, and every run
+    // is checked against it before it is kept
+    let too_large = || format!("RLE body does not fit in the {width}x{height} of its header");
+    let mut runs = vec![];
+    let (mut row, mut col) = (0usize, 0usize);
+    let mut count = None::<usize>;
+    'body: for line in lines {
+        for tag in line.chars() {
+            if let Some(digit) = tag.to_digit(10) {
+                count = count
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|count| count.checked_add(digit as usize));
+                if count.is_none() {
+                    return Err(format!("run count in `{line}` is too large"));
+                }
+                continue;
+            }
+            let run = count.take().unwrap_or(1);
+            match tag {
+                '!' => break 'body,
+                // Rows past the last one may still end, as long as they stay empty
+                '$' => (row, col) = (row.saturating_add(run), 0),
+                tag if tag.is_ascii_alphabetic() || tag == '.' => {
+                    let end = col.checked_add(run).filter(|&end| end <= width);
+                    if run > 0 && (row >= height || end.is_none()) {
+                        return Err(too_large());
+                    }
+                    if tag != 'b' && tag != '.' && run > 0 {
+                        runs.push((row, col, run));
+                    }
+                    col = end.unwrap_or(col);
+                }
+                tag if tag.is_whitespace() => {}
+                _ => return Err(format!("unexpected `{tag}` in RLE body")),
+            }
+        }
+    }
+
+    Ok(Pattern {
+        height,
+        width,
+        runs,
+    })
+}
+
+/// One row per line, `.` for a dead and `O` or `*` for a live cell
+fn parse_cells<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Pattern, String> {
+    let rows = lines
+        .map(|line| {
+            line.chars()
+                .map(|cell| match cell {
+                    '.' => Ok(0),
+                    'O' | '*' => Ok(1),
+                    _ => Err(format!("unexpected `{cell}` in plaintext pattern")),
+                })
+                .collect()
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Pattern::from_rows(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The pattern's cells, placed in a grid of its own size
+    fn cells(text: &str) -> Vec<Vec<u8>> {
+        let pattern: Pattern = text.parse().unwrap();
+        pattern
+            .place((pattern.height(), pattern.width()), (0, 0))
+            .unwrap()
+    }
+
+    fn glider() -> Vec<Vec<u8>> {
+        vec![vec![0, 1, 0], vec![0, 0, 1], vec![1, 1, 1]]
+    }
+
+    #[test]
+    fn parses_rle() {
+        let rle = "#N Glider\n#C a comment\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+        assert_eq!(cells(rle), glider());
+        // Runs may be split across lines and trailing dead cells left out
+        assert_eq!(
+            cells("x = 4, y = 2\n2o\n$b\no!"),
+            [[1, 1, 0, 0], [0, 1, 0, 0]]
+        );
+        // Rows may end past the last one as long as they stay empty
+        assert_eq!(cells("x = 1, y = 1\no3$!"), [[1]]);
+        // Nothing after the `!` is read
+        assert_eq!(cells("x = 2, y = 1\nbo!2o"), [[0, 1]]);
+    }
+
+    #[test]
+    fn rejects_bad_rle() {
+        for (rle, error) in [
+            ("x = 3\nbo!", "header"),
+            ("x = 3, y = three\nbo!", "header"),
+            ("x = 2, y = 1\n3o!", "does not fit"),
+            ("x = 2, y = 1\n3b!", "does not fit"),
+            ("x = 2, y = 1\no$o!", "does not fit"),
+            ("x = 2, y = 1\n99999999999999999999999o!", "too large"),
+            ("x = 2, y = 1\no?!", "unexpected `?`"),
+        ] {
+            let result = rle.parse::<Pattern>();
+            let Err(message) = result else {
+                panic!("`{rle}` has to be rejected");
+            };
+            assert!(message.contains(error), "`{rle}`: {message}");
+        }
+    }
+
+    #[test]
+    fn keeps_only_the_runs_of_huge_rle_headers() {
+        let size = usize::MAX / 2;
+        let pattern: Pattern = format!("x = {size}, y = {size}\n{}bo!", size - 1)
+            .parse()
+            .unwrap();
+        assert_eq!((pattern.height(), pattern.width()), (size, size));
+        assert_eq!(pattern.runs, [(0, size - 1, 1)]);
+
+        let error = pattern.place((3, 3), (0, 0)).unwrap_err();
+        assert!(error.contains("does not fit"), "{error}");
+        let error = pattern.place((3, 3), (usize::MAX, 0)).unwrap_err();
+        assert!(error.contains("does not fit"), "{error}");
+    }
+
+    #[test]
+    fn parses_cells() {
+        let plaintext = "!Name: Glider\n.O.\n..*\nOOO\n";
+        assert_eq!(cells(plaintext), glider());
+        // Short rows are padded, and empty lines are dead rows except at the end
+        assert_eq!(cells("O\n\n.O.\n\n"), [[1, 0, 0], [0, 0, 0], [0, 1, 0]]);
+    }
+
+    #[test]
+    fn rejects_bad_cells() {
+        let message = ".O.\n.x.".parse::<Pattern>().unwrap_err();
+        assert!(message.contains("unexpected `x`"), "{message}");
+    }
+
+    #[test]
+    fn parses_life_106() {
+        // Coordinates are `x y`, and the pattern starts at the top left cell
+        let life = "#Life 1.06\n#D Glider\n0 -1\n1 0\n-1 1\n0 1\n1 1\n";
+        assert_eq!(cells(life), glider());
+        assert_eq!(cells("#Life 1.06\n"), Vec::<Vec<u8>>::new());
+    }
+
+    #[test]
+    fn rejects_bad_life_106() {
+        for life in ["#Life 1.06\n0 0\n1", "#Life 1.06\n0 0 0", "#Life 1.06\na b"] {
+            let message = life.parse::<Pattern>().unwrap_err();
+            assert!(message.contains("not a Life 1.06"), "`{life}`: {message}");
+        }
+        let life = format!("#Life 1.06\n{} 0\n{} 0", i64::MIN, i64::MAX);
+        let message = life.parse::<Pattern>().unwrap_err();
+        assert!(message.contains("fit in memory"), "{message}");
+    }
+}
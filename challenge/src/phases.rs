@@ -0,0 +1,167 @@
+use serde::Serialize;
+use std::fmt;
+use std::ops::Add;
+
+/// One phase of a game, from the client's key generation to its
+/// verification of the result
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Phase {
+    Keygen,
+    Encrypt,
+    Evaluate,
+    Decrypt,
+    Verify,
+}
+
+/// Seconds spent in every phase of a game
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct Phases {
+    pub(crate) keygen: f64,
+    pub(crate) encrypt: f64,
+    /// Everything between the client handing its grid over and getting the
+    /// result back, including serialization and transfer if there are any
+    pub(crate) evaluate: f64,
+    pub(crate) decrypt: f64,
+    /// Simulating the expected grid and comparing it with the decrypted one
+    pub(crate) verify: f64,
+    /// Seconds of every generation inside `evaluate`, only known when the
+    /// server runs in the same process
+    pub(crate) generations: Vec<f64>,
+    /// Idle time of the evaluation, only known for a pipelined server in
+    /// the same process
+    pub(crate) idle: Option<Idle>,
+    /// Grid storage every generation allocated and copied, known when
+    /// `generations` is
+    pub(crate) traffic: Vec<Traffic>,
+}
+
+/// Thread-seconds the workers of a pipelined evaluation spent waiting
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub(crate) struct Idle {
+    pub(crate) pipelined: f64,
+    /// What a barrier after every stage would have left idle at least, a
+    /// lower bound as no barrier run is timed
+    #[serde(rename = "barrier_lower_bound")]
+    pub(crate) barrier: f64,
+}
+
+impl Idle {
+    /// Thread-seconds pipelining saved at least, a lower-bound estimate
+    /// that is never negative
+    ///
+    /// The bounded barrier run leaves no gaps between tasks, so it can come
+    /// out below the measured run when there was little to save.
+    pub(crate) fn removed(&self) -> f64 {
+        (self.barrier - self.pipelined).max(0.0)
+    }
+}
+
+/// What a server's grid storage cost in one generation
+///
+/// Only grid-sized buffers and whole ciphertexts count, not what the
+/// homomorphic operations allocate for their results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct Traffic {
+    /// Grid-sized buffers allocated
+    pub(crate) allocations: u64,
+    /// Bytes of ciphertexts cloned
+    pub(crate) copied: u64,
+}
+
+impl Add for Traffic {
+    type Output = Traffic;
+
+    fn add(self, other: Traffic) -> Traffic {
+        Traffic {
+            allocations: self.allocations + other.allocations,
+            copied: self.copied + other.copied,
+        }
+    }
+}
+
+impl Phases {
+    pub(crate) fn add(&mut self, phase: Phase, seconds: f64) {
+        *match phase {
+            Phase::Keygen => &mut self.keygen,
+            Phase::Encrypt => &mut self.encrypt,
+            Phase::Evaluate => &mut self.evaluate,
+            Phase::Decrypt => &mut self.decrypt,
+            Phase::Verify => &mut self.verify,
+        } += seconds;
+    }
+
+    /// Phase by phase and generation by generation mean of several runs
+    pub(crate) fn mean(runs: &[Phases]) -> Phases {
+        let count = runs.len().max(1) as f64;
+        let mut mean = Phases::default();
+        for run in runs {
+            mean.keygen += run.keygen / count;
+            mean.encrypt += run.encrypt / count;
+            mean.evaluate += run.evaluate / count;
+            mean.decrypt += run.decrypt / count;
+            mean.verify += run.verify / count;
+            if mean.generations.len() < run.generations.len() {
+                mean.generations.resize(run.generations.len(), 0.0);
+            }
+            for (total, seconds) in mean.generations.iter_mut().zip(&run.generations) {
+                *total += seconds / count;
+            }
+            // Every run allocates and copies the same
+            if mean.traffic.is_empty() {
+                mean.traffic = run.traffic.clone();
+            }
+            if let Some(idle) = run.idle {
+                let total = mean.idle.get_or_insert_default();
+                total.pipelined += idle.pipelined / count;
+                total.barrier += idle.barrier / count;
+            }
+        }
+        mean
+    }
+}
+
+impl fmt::Display for Phases {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "keygen {:.3}s, encrypt {:.3}s, evaluate {:.3}s, decrypt {:.3}s, verify {:.3}s",
+            self.keygen, self.encrypt, self.evaluate, self.decrypt, self.verify
+        )?;
+        if !self.generations.is_empty() {
+            let generations: Vec<String> = self
+                .generations
+                .iter()
+                .map(|seconds| format!("{seconds:.3}s"))
+                .collect();
+            write!(f, ", generations [{}]", generations.join(", "))?;
+        }
+        if !self.traffic.is_empty() {
+            let allocations: Vec<String> = self
+                .traffic
+                .iter()
+                .map(|traffic| traffic.allocations.to_string())
+                .collect();
+            let copied: Vec<String> = self
+                .traffic
+                .iter()
+                .map(|traffic| format!("{} B", traffic.copied))
+                .collect();
+            write!(
+                f,
+                ", grid allocations [{}], copied [{}]",
+                allocations.join(", "),
+                copied.join(", ")
+            )?;
+        }
+        if let Some(idle) = self.idle {
+            write!(
+                f,
+                ", idle {:.3} thread-s (at least {:.3} removed by pipelining, a lower-bound \
+                 estimate)",
+                idle.pipelined,
+                idle.removed()
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use crate::boundary::Boundary;
+use crate::client::EncryptedGrid;
+use crate::grid::Grid;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use tfhe::prelude::CiphertextList;
+use tfhe::{
+    CompactCiphertextList, CompactCiphertextListBuilder, CompactCiphertextListExpander,
+    CompactPublicKey, FheBool, FheUint8, ServerKey,
+};
+
+/// A cell type third parties can encrypt with nothing but the client's
+/// compact public key
+pub(crate) trait PublicCell: Sized {
+    fn push_cell(builder: &mut CompactCiphertextListBuilder, cell: u8);
+    fn expand_cell(
+        expander: &CompactCiphertextListExpander,
+        k: usize,
+    ) -> Result<Option<Self>, Box<dyn Error>>;
+}
+
+impl PublicCell for FheUint8 {
+    fn push_cell(builder: &mut CompactCiphertextListBuilder, cell: u8) {
+        builder.push(cell);
+    }
+
+    fn expand_cell(
+        expander: &CompactCiphertextListExpander,
+        k: usize,
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        Ok(expander.get(k)?)
+    }
+}
+
+impl PublicCell for FheBool {
+    fn push_cell(builder: &mut CompactCiphertextListBuilder, cell: u8) {
+        builder.push(cell == 1);
+    }
+
+    fn expand_cell(
+        expander: &CompactCiphertextListExpander,
+        k: usize,
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        Ok(expander.get(k)?)
+    }
+}
+
+/// A rectangular region of the grid, encrypted by a third party
+///
+/// The cells are packed into a single compact list, which only the server
+/// key can expand into ciphertexts the servers evaluate.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Contribution {
+    /// Row and column of the region's top left cell in the grid
+    offset: (usize, usize),
+    /// Rows and columns of the region
+    size: (usize, usize),
+    cells: CompactCiphertextList,
+}
+
+impl Contribution {
+    /// Encrypt `region`, whose top left cell goes to `offset`
+    ///
+    /// The rows of `region` have to be non-empty and of the same length, and
+    /// their cells 0 or 1.
+    pub(crate) fn encrypt<C: PublicCell>(
+        public_key: &CompactPublicKey,
+        region: &[Vec<u8>],
+        offset: (usize, usize),
+    ) -> Result<Self, Box<dyn Error>> {
+        let cols = region.first().map_or(0, Vec::len);
+        if cols == 0 || region.iter().any(|row| row.len() != cols) {
+            return Err("a contribution has to be a non-empty rectangle".into());
+        }
+        if region.iter().flatten().any(|&cell| cell > 1) {
+            return Err("cells have to be 0 or 1".into());
+        }
+
+        let mut builder = CompactCiphertextList::builder(public_key);
+        for &cell in region.iter().flatten() {
+            C::push_cell(&mut builder, cell);
+        }
+        Ok(Contribution {
+            offset,
+            size: (region.len(), cols),
+            cells: builder.build_packed(),
+        })
+    }
+}
+
+/// Build the `m * n` grid out of the cells of `contributions`, which have to
+/// cover every cell exactly once
+///
+/// Expanding the lists takes the server key, packed cells are split back
+/// into ciphertexts of their own with it.
+pub(crate) fn assemble<C: PublicCell>(
+    (m, n): (usize, usize),
+    boundary: Boundary,
+    contributions: &[Contribution],
+    server_key: &ServerKey,
+) -> Result<EncryptedGrid<C>, Box<dyn Error>> {
+    let mut cells: Vec<Option<C>> = (0..m * n).map(|_| None).collect();
+    for contribution in contributions {
+        let ((row, col), (rows, cols)) = (contribution.offset, contribution.size);
+        if row + rows > m || col + cols > n {
+            return Err(format!(
+                "the {rows}x{cols} contribution at ({row}, {col}) does not fit an {m}x{n} grid"
+            )
+            .into());
+        }
+        if contribution.cells.len() != rows * cols {
+            return Err(format!(
+                "the contribution at ({row}, {col}) holds {} cells, expected {rows}x{cols}",
+                contribution.cells.len()
+            )
+            .into());
+        }
+
+        let expander =
+            tfhe::with_server_key_as_context(server_key.clone(), || contribution.cells.expand())?;
+        for k in 0..rows * cols {
+            let (i, j) = (row + k / cols, col + k % cols);
+            let cell = C::expand_cell(&expander, k)?
+                .ok_or_else(|| format!("the contribution at ({row}, {col}) lacks cell {k}"))?;
+            if cells[i * n + j].replace(cell).is_some() {
+                return Err(format!("cell ({i}, {j}) is contributed more than once").into());
+            }
+        }
+    }
+
+    let cells = cells
+        .into_iter()
+        .enumerate()
+        .map(|(k, cell)| {
+            cell.ok_or_else(|| format!("no one contributed cell ({}, {})", k / n, k % n))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Grid::from_cells((m, n), boundary, cells))
+}
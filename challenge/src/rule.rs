@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A Life-like rule, i.e. which neighbor counts make a cell born or survive
+///
+/// Written in the usual B/S notation, e.g. "B3/S23" for Conway's Life,
+/// "B36/S23" for HighLife or "B2/S" for Seeds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Rule {
+    birth: [bool; 9],
+    survival: [bool; 9],
+}
+
+impl Rule {
+    /// Whether a dead cell with `count` live neighbors comes alive
+    pub(crate) fn born(&self, count: usize) -> bool {
+        self.birth.get(count).copied().unwrap_or(false)
+    }
+
+    /// Whether a live cell with `count` live neighbors stays alive
+    pub(crate) fn survives(&self, count: usize) -> bool {
+        self.survival.get(count).copied().unwrap_or(false)
+    }
+
+    /// Next state of a cell, both as 0 or 1
+    pub(crate) fn next(&self, cell: u8, count: u8) -> u8 {
+        let count = count as usize;
+        if cell == 1 {
+            self.survives(count) as u8
+        } else {
+            self.born(count) as u8
+        }
+    }
+
+    /// Neighbor counts that make a dead cell come alive
+    pub(crate) fn birth(&self) -> impl Iterator<Item = u8> {
+        counts(self.birth)
+    }
+
+    /// Neighbor counts that keep a live cell alive
+    pub(crate) fn survival(&self) -> impl Iterator<Item = u8> {
+        counts(self.survival)
+    }
+}
+
+#[cfg(test)]
+impl Rule {
+    /// B3/S23, the rules with no and with every count, and `count` more
+    /// Life-like rules drawn at random, always the same ones
+    pub(crate) fn sample(count: usize) -> Vec<Rule> {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut rules = vec![Rule::default(), "B/S".parse().unwrap()];
+        rules.push("B012345678/S012345678".parse().unwrap());
+        rules.extend((0..count).map(|_| {
+            let masks: u32 = rng.random_range(0..1 << 18);
+            Rule {
+                birth: std::array::from_fn(|count| masks >> count & 1 == 1),
+                survival: std::array::from_fn(|count| masks >> (9 + count) & 1 == 1),
+            }
+        }));
+        rules
+    }
+}
+
+fn counts(set: [bool; 9]) -> impl Iterator<Item = u8> {
+    (0..9u8).filter(move |&count| set[count as usize])
+}
+
+impl Default for Rule {
+    /// Conway's Game of Life, B3/S23
+    fn default() -> Self {
+        "B3/S23".parse().unwrap()
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Accept both "B3/S23" and "S23/B3"
+        let parts = s.split_once('/').map(|(first, second)| {
+            if first.starts_with(['S', 's']) {
+                (second, first)
+            } else {
+                (first, second)
+            }
+        });
+        let Some((birth, survival)) = parts else {
+            return Err(format!("rule `{s}` is not in B/S notation, e.g. `B3/S23`"));
+        };
+
+        let parse = |part: &str, prefix: [char; 2]| {
+            let digits = part
+                .strip_prefix(prefix)
+                .ok_or_else(|| format!("rule `{s}` is not in B/S notation, e.g. `B3/S23`"))?;
+
+            let mut set = [false; 9];
+            for digit in digits.chars() {
+                match digit.to_digit(10) {
+                    Some(count) if count <= 8 => set[count as usize] = true,
+                    _ => return Err(format!("`{digit}` in rule `{s}` is not a count in 0..=8")),
+                }
+            }
+            Ok(set)
+        };
+
+        Ok(Rule {
+            birth: parse(birth, ['B', 'b'])?,
+            survival: parse(survival, ['S', 's'])?,
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "B")?;
+        for count in self.birth() {
+            write!(f, "{count}")?;
+        }
+        write!(f, "/S")?;
+        for count in self.survival() {
+            write!(f, "{count}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(rule: &str) -> Rule {
+        rule.parse().unwrap()
+    }
+
+    #[test]
+    fn accepts_either_order() {
+        assert_eq!(parse("S23/B3"), parse("B3/S23"));
+        assert_eq!(parse("S/B2"), parse("B2/S"));
+    }
+
+    #[test]
+    fn accepts_lowercase() {
+        assert_eq!(parse("b3/s23"), Rule::default());
+        assert_eq!(parse("s23/b36"), parse("B36/S23"));
+    }
+
+    #[test]
+    fn repeated_digits_count_once() {
+        assert_eq!(parse("B33/S2323"), Rule::default());
+    }
+
+    #[test]
+    fn rejects_counts_above_8() {
+        for rule in ["B9/S23", "B3/S239"] {
+            let message = rule.parse::<Rule>().unwrap_err();
+            assert!(message.contains("`9`"), "`{rule}`: {message}");
+        }
+    }
+
+    #[test]
+    fn rejects_other_notations() {
+        for rule in ["B3S23", "23/3", "B3/23", "X3/S23", "B3/S2a", ""] {
+            assert!(rule.parse::<Rule>().is_err(), "`{rule}` has to be rejected");
+        }
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        assert_eq!(Rule::default().to_string(), "B3/S23");
+        for rule in Rule::sample(64) {
+            assert_eq!(parse(&rule.to_string()), rule);
+        }
+        // Display writes counts in order, once each
+        assert_eq!(parse("s32/b33").to_string(), "B3/S23");
+    }
+}
@@ -1,66 +1,383 @@
 use crate::client::EncryptedGrid;
-use tfhe::prelude::*;
-use tfhe::{FheUint8, ServerKey, set_server_key};
+use crate::dataflow::Dataflow;
+use crate::grid::Grid;
+use crate::ops::{Mock, OpCounts, Ops, Tfhe};
+use crate::phases::{Idle, Traffic};
+use crate::rule::Rule;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::Mutex;
+use tfhe::{ServerKey, set_server_key};
 
-pub(crate) struct Server {
-    server_key: ServerKey,
-    grid: EncryptedGrid,
+/// What every server does with the grid it was given, whatever its cells
+pub(crate) trait Evolve {
+    type Cell;
+
+    /// Run `steps` generations on the server's grid, which a server only
+    /// has for one run
+    fn run(&self, steps: u32) -> EncryptedGrid<Self::Cell>;
+
+    /// Like `run`, but keep every generation, starting with the initial grid
+    fn generations(&self, steps: u32) -> Vec<EncryptedGrid<Self::Cell>>;
+
+    /// Seconds every generation took, in the order they were run
+    fn generation_times(&self) -> Vec<f64>;
+
+    /// What the grid storage allocated and copied in every generation
+    fn traffic(&self) -> Vec<Traffic>;
+
+    /// Idle time of the last run, for servers that pipeline generations
+    fn idle(&self) -> Option<Idle> {
+        None
+    }
+}
+
+/// Server that keeps every cell as an 8-bit integer
+///
+/// Every generation runs in two stages. Neighbor counts are built from sums
+/// of three cells shared between neighboring cells, then the rule compares
+/// every count with the counts in the birth and survival sets. The stages of
+/// all generations are pipelined by a `Dataflow`, without barriers between
+/// them. The circuit is written against `Ops`, so it also runs on the
+/// cleartext `Mock`.
+pub(crate) struct Server<O: Ops = Tfhe> {
+    ops: O,
+    /// Operations of the neighbor count stage, apart from `ops` so that the
+    /// mock counts the stage on its own
+    count_ops: O,
+    rule: Rule,
+    pool: ThreadPool,
+    /// Taken by the first run
+    grid: Mutex<Option<EncryptedGrid<O::Cell>>>,
+    generation_times: Mutex<Vec<f64>>,
+    traffic: Mutex<Vec<Traffic>>,
+    idle: Mutex<Option<Idle>>,
 }
 
 impl Server {
-    pub(crate) fn new(server_key: ServerKey, grid: EncryptedGrid) -> Self {
-        Server { server_key, grid }
+    /// Create a server evaluating on `threads` worker threads,
+    /// or on every available core if `threads` is 0
+    pub(crate) fn new(
+        server_key: ServerKey,
+        grid: EncryptedGrid,
+        rule: Rule,
+        threads: usize,
+    ) -> Self {
+        let pool = thread_pool(server_key, threads);
+        Server {
+            ops: Tfhe,
+            count_ops: Tfhe,
+            rule,
+            pool,
+            grid: Mutex::new(Some(grid)),
+            generation_times: Mutex::default(),
+            traffic: Mutex::default(),
+            idle: Mutex::default(),
+        }
     }
+}
 
-    pub(crate) fn run(&self, steps: u32) -> EncryptedGrid {
-        let mut current_grid = self.grid.clone();
-        for _ in 0..steps {
-            current_grid = self.step(&current_grid);
+impl Server<Mock> {
+    /// Create a server evaluating the same circuit on a cleartext grid,
+    /// counting operations instead of encrypting
+    pub(crate) fn mock(grid: Grid<u8>, rule: Rule, threads: usize) -> Self {
+        let pool = plain_thread_pool(threads);
+        Server {
+            ops: Mock::default(),
+            count_ops: Mock::default(),
+            rule,
+            pool,
+            grid: Mutex::new(Some(grid)),
+            generation_times: Mutex::default(),
+            traffic: Mutex::default(),
+            idle: Mutex::default(),
         }
-        current_grid
     }
 
-    fn step(&self, grid: &EncryptedGrid) -> EncryptedGrid {
-        let mut new_grid = vec![];
-        for i in 0..self.grid.len() {
-            let mut row = vec![];
-            for j in 0..self.grid[i].len() {
-                row.push(self.update_cell(i, j, grid));
+    /// Operations evaluated so far, by both stages
+    pub(crate) fn counts(&self) -> OpCounts {
+        self.count_ops.counts() + self.ops.counts()
+    }
+
+    /// Operations the neighbor count stage evaluated so far
+    pub(crate) fn neighbor_counts(&self) -> OpCounts {
+        self.count_ops.counts()
+    }
+}
+
+impl<O: Ops> Evolve for Server<O> {
+    type Cell = O::Cell;
+
+    fn run(&self, steps: u32) -> EncryptedGrid<O::Cell> {
+        self.evolve(steps, false).pop().unwrap()
+    }
+
+    fn generations(&self, steps: u32) -> Vec<EncryptedGrid<O::Cell>> {
+        self.evolve(steps, true)
+    }
+
+    fn generation_times(&self) -> Vec<f64> {
+        self.generation_times.lock().unwrap().clone()
+    }
+
+    fn traffic(&self) -> Vec<Traffic> {
+        self.traffic.lock().unwrap().clone()
+    }
+
+    fn idle(&self) -> Option<Idle> {
+        *self.idle.lock().unwrap()
+    }
+}
+
+impl<O: Ops> Server<O> {
+    /// Run `steps` generations, keeping all of them or only the last one
+    ///
+    /// The initial cells are moved into the dataflow, and every other value
+    /// is dropped once the tasks reading it are done, so only the
+    /// generations in flight take up memory.
+    fn evolve(&self, steps: u32, keep_all: bool) -> Vec<EncryptedGrid<O::Cell>> {
+        let grid = self.grid.lock().unwrap().take();
+        let grid = grid.expect("the server already ran its grid");
+
+        let (mut dataflow, generations) = self.dataflow(&grid, steps);
+        let kept = if keep_all {
+            &generations[..]
+        } else {
+            &generations[generations.len() - 1..]
+        };
+        for &cell in kept.iter().flat_map(Grid::cells) {
+            dataflow.keep(cell);
+        }
+        let sources = grid.into_cells();
+        let (mut values, schedule, run_traffic) =
+            dataflow.run(&self.pool, sources, |task, inputs| {
+                self.evaluate(task, inputs)
+            });
+
+        // Generations overlap, each one takes from the end of the one
+        // before until its own last cell is done
+        let ends = schedule.stage_ends();
+        *self.generation_times.lock().unwrap() = (1..=steps as usize)
+            .map(|g| ends[2 * g] - ends[2 * g - 2])
+            .collect();
+        *self.idle.lock().unwrap() = Some(schedule.idle());
+
+        // The dataflow allocates its tables once for the whole run, which
+        // counts towards the first generation, and every kept grid towards
+        // the generation it is kept in
+        let mut traffic = vec![Traffic::default(); steps as usize];
+        for g in generations.len() - kept.len()..generations.len() {
+            if let Some(traffic) = traffic.get_mut(g.max(1) - 1) {
+                traffic.allocations += 1;
             }
-            new_grid.push(row);
         }
-        new_grid
+        if let Some(first) = traffic.first_mut() {
+            *first = *first + run_traffic;
+        }
+        *self.traffic.lock().unwrap() = traffic;
+
+        kept.iter()
+            .map(|cells| cells.map(|&cell| values[cell].take().unwrap()))
+            .collect()
     }
 
-    fn update_cell(&self, x: usize, y: usize, grid: &EncryptedGrid) -> FheUint8 {
-        set_server_key(self.server_key.clone());
+    /// The tasks of `steps` generations on the shape and boundary of `grid`
+    ///
+    /// Every generation takes two stages, the row sums of every cell and
+    /// then the update of every cell from its count. Stage 0 holds the
+    /// initial grid and generation `g` ends with stage `2 * g`. A cell of
+    /// the next generation only waits for the row sums around it, and a row
+    /// sum for the three cells it adds up, so the scheduler can start on a
+    /// generation while the one before is still being evaluated elsewhere.
+    ///
+    /// # Returns
+    /// The tasks and, for every generation, the grid of the task of every
+    /// cell.
+    fn dataflow<T>(&self, grid: &Grid<T>, steps: u32) -> (Dataflow<Task>, Vec<Grid<usize>>) {
+        let mut dataflow = Dataflow::default();
+        let mut cells = grid.map(|_| dataflow.source(0));
+        let mut generations = vec![cells.clone()];
 
-        let mut count = FheUint8::try_encrypt_trivial(0u8).unwrap();
-        for dx in [-1isize, 0, 1].iter() {
-            for dy in [-1isize, 0, 1].iter() {
-                if *dx == 0 && *dy == 0 {
-                    continue;
+        for g in 1..=steps as usize {
+            let row_sums = Grid::from_fn(grid.dimensions(), grid.boundary(), |i, j| {
+                let columns: Vec<usize> = cells.columns_around(j).map(|y| cells[(i, y)]).collect();
+                // A single column between dead borders is its own sum
+                if columns.len() == 1 {
+                    columns[0]
+                } else {
+                    dataflow.add(Task::RowSum, 2 * g - 1, columns)
                 }
+            });
+            cells = Grid::from_fn(grid.dimensions(), grid.boundary(), |i, j| {
+                let inputs = std::iter::once(cells[(i, j)])
+                    .chain(cells.rows_around(i).map(|x| row_sums[(x, j)]))
+                    .collect();
+                dataflow.add(Task::Update, 2 * g, inputs)
+            });
+            generations.push(cells.clone());
+        }
+        (dataflow, generations)
+    }
+
+    fn evaluate(&self, task: &Task, inputs: &[&O::Cell]) -> O::Cell {
+        match task {
+            Task::RowSum => self.sum(inputs),
+            Task::Update => {
+                let (cell, row_sums) = inputs.split_first().unwrap();
+                let count = self.count_neighbors(cell, row_sums);
+                self.update_cell(cell, &count)
+            }
+        }
+    }
+
+    /// Count the live neighbors of a cell
+    ///
+    /// Every row is first summed three cells at a time, the sum around
+    /// `(i, j)` is then three of these row sums minus the cell itself. Each
+    /// row sum is shared by the three rows around it, so this takes five
+    /// additions per cell instead of eight. The boundary resolves rows and
+    /// columns independently, which makes the two passes add up exactly the
+    /// neighbors `Grid::neighbors` yields.
+    fn count_neighbors(&self, cell: &O::Cell, row_sums: &[&O::Cell]) -> O::Cell {
+        match row_sums {
+            // A single row between dead borders
+            [row_sum] => self.count_ops.sub(row_sum, cell),
+            _ => {
+                let mut count = self.sum(row_sums);
+                self.count_ops.sub_assign(&mut count, cell);
+                count
+            }
+        }
+    }
+
+    /// Add up two or more cells, starting from the first two instead of a
+    /// trivial zero and without cloning any of them
+    fn sum(&self, cells: &[&O::Cell]) -> O::Cell {
+        let ops = &self.count_ops;
+        let mut acc = ops.add(cells[0], cells[1]);
+        for cell in &cells[2..] {
+            ops.add_assign(&mut acc, cell);
+        }
+        acc
+    }
+
+    fn update_cell(&self, cell: &O::Cell, count: &O::Cell) -> O::Cell {
+        let ops = &self.ops;
+
+        // Counts used by both birth and survival are only compared once
+        let mut equals: [Option<O::Bool>; 9] = Default::default();
+        for k in self.rule.birth().chain(self.rule.survival()) {
+            equals[k as usize].get_or_insert_with(|| ops.eq(count, k));
+        }
+        let matches_any = |counts: &mut dyn Iterator<Item = u8>| {
+            counts
+                .filter_map(|k| equals[k as usize].clone())
+                .reduce(|acc, eq| ops.or(&acc, &eq))
+                .unwrap_or_else(|| ops.constant_bool(false))
+        };
+
+        let zero = ops.constant(0);
+        let one = ops.constant(1);
 
-                let nx = x.wrapping_add(*dx as usize);
-                let ny = y.wrapping_add(*dy as usize);
+        let alive = ops.eq(cell, 1);
+        let born = ops.select(&matches_any(&mut self.rule.birth()), &one, &zero);
+        let survives = ops.select(&matches_any(&mut self.rule.survival()), &one, &zero);
 
-                if nx < grid.len() && ny < grid[nx].len() {
-                    count += grid[nx][ny].clone();
+        ops.select(&alive, &survives, &born)
+    }
+}
+
+/// One task of the pipelined evaluation, see `Server::dataflow`
+enum Task {
+    /// The cells of a row around a column, which are its inputs
+    RowSum,
+    /// A cell of the next generation, its inputs are the cell itself
+    /// followed by the row sums around it
+    Update,
+}
+
+/// Build the work-stealing pool cells are evaluated on
+///
+/// Every worker installs its own copy of the server key once when it starts,
+/// so cell updates never have to touch the key themselves.
+pub(crate) fn thread_pool(server_key: ServerKey, threads: usize) -> ThreadPool {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .start_handler(move |_| set_server_key(server_key.clone()))
+        .build()
+        .expect("failed to build the evaluation thread pool")
+}
+
+/// Build a pool for servers whose cell updates carry their own key, or none
+pub(crate) fn plain_thread_pool(threads: usize) -> ThreadPool {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .expect("failed to build the evaluation thread pool")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::Boundary;
+    use crate::client::{next_generation, random_grid};
+
+    #[test]
+    fn mock_follows_the_rule() {
+        let rules = ["B3/S23", "B36/S23", "B2/S", "B1357/S1357", "B/S012345678"];
+        let boundaries = [Boundary::Dead, Boundary::Torus, Boundary::Reflect];
+        // Single rows and columns see their own cells beyond the edges
+        let dimensions = [(1, 1), (1, 6), (5, 1), (7, 9)];
+        let steps = 4;
+
+        for (seed, rule) in rules.iter().enumerate() {
+            let rule: Rule = rule.parse().unwrap();
+            for &boundary in &boundaries {
+                for &(m, n) in &dimensions {
+                    let grid = Grid::from_rows(random_grid(m, n, seed as u64), boundary);
+                    let server = Server::mock(grid.clone(), rule, 2);
+
+                    let mut expected = grid;
+                    for (step, actual) in server.generations(steps).into_iter().enumerate() {
+                        assert_eq!(
+                            actual, expected,
+                            "{rule} on a {m}x{n} {boundary} grid differs at generation {step}"
+                        );
+                        expected = next_generation(&expected, rule);
+                    }
                 }
             }
         }
+    }
+
+    #[test]
+    fn neighbor_count_stage_takes_five_additions_per_cell() {
+        let (m, n, steps) = (6, 7, 3);
+        let grid = Grid::from_rows(random_grid(m, n, 0), Boundary::Torus);
+        let server = Server::mock(grid, Rule::default(), 1);
+        server.run(steps);
+
+        // Two for the row sum, two to add the row sums and one to subtract
+        // the cell, instead of seven to add up eight neighbors
+        let cell_updates = (m * n * steps) as u64;
+        assert_eq!(server.neighbor_counts().additions, 5 * cell_updates);
+    }
 
-        let cell = &grid[x][y];
-        let zero = FheUint8::try_encrypt_trivial(0u8).unwrap();
-        let one = FheUint8::try_encrypt_trivial(1u8).unwrap();
-        let two = FheUint8::try_encrypt_trivial(2u8).unwrap();
-        let three = FheUint8::try_encrypt_trivial(3u8).unwrap();
+    #[test]
+    fn traffic_counts_the_tables_and_kept_grids() {
+        let grid = Grid::from_rows(random_grid(4, 4, 0), Boundary::Dead);
+        let allocations = |server: &Server<Mock>| -> Vec<u64> {
+            let traffic = server.traffic();
+            assert!(traffic.iter().all(|traffic| traffic.copied == 0));
+            traffic.iter().map(|traffic| traffic.allocations).collect()
+        };
 
-        let alive = cell.eq(&one);
-        let eq_three = count.eq(&three).select(&one, &zero);
-        let eq_two_or_three = (count.eq(&two) | count.eq(&three)).select(&one, &zero);
+        let server = Server::mock(grid.clone(), Rule::default(), 1);
+        server.run(3);
+        assert_eq!(allocations(&server), [2, 0, 1]);
 
-        alive.if_then_else(&eq_two_or_three, &eq_three)
+        // The initial grid is kept along with the first generation
+        let server = Server::mock(grid, Rule::default(), 1);
+        server.generations(3);
+        assert_eq!(allocations(&server), [4, 1, 1]);
     }
 }
@@ -0,0 +1,126 @@
+use crate::cell_server::{CellServer, CellUpdate};
+use crate::client::EncryptedGrid;
+use crate::grid::Grid;
+use crate::rule::Rule;
+use crate::server::plain_thread_pool;
+use tfhe::shortint::parameters::v1_2::V1_2_PARAM_MESSAGE_2_CARRY_3_KS_PBS_GAUSSIAN_2M128;
+use tfhe::shortint::server_key::LookupTableOwned;
+use tfhe::shortint::{Ciphertext, ClassicPBSParameters, ServerKey};
+
+/// Parameters of the shortint keys
+///
+/// The whole neighborhood is packed as `2 * count + alive`, i.e. values up to
+/// 17, so the 2 message bits plus 3 carry bits give exactly enough room. The
+/// packing has a noise 2-norm of `sqrt(8 * 2^2 + 1) < 6`, well below the 10
+/// these parameters allow before a bootstrap.
+pub(crate) const SHORTINT_PARAMETERS: ClassicPBSParameters =
+    V1_2_PARAM_MESSAGE_2_CARRY_3_KS_PBS_GAUSSIAN_2M128;
+
+/// Server that evaluates each cell with a single programmable bootstrap
+///
+/// Neighbors are summed with leveled additions into the carry space, and the
+/// rule is a lookup table over the packed (count, alive) value.
+pub(crate) type ShortintServer = CellServer<ShortintUpdate>;
+
+/// The cell update of a `ShortintServer`
+pub(crate) struct ShortintUpdate {
+    server_key: ServerKey,
+    lookup_table: LookupTableOwned,
+}
+
+impl ShortintServer {
+    /// Create a server evaluating on `threads` worker threads,
+    /// or on every available core if `threads` is 0
+    pub(crate) fn new(
+        server_key: ServerKey,
+        grid: EncryptedGrid<Ciphertext>,
+        rule: Rule,
+        threads: usize,
+    ) -> Self {
+        let lookup_table = server_key.generate_lookup_table(|packed| next_state(rule, packed));
+        let update = ShortintUpdate {
+            server_key,
+            lookup_table,
+        };
+        CellServer::with_update(update, plain_thread_pool(threads), grid)
+    }
+}
+
+impl CellUpdate for ShortintUpdate {
+    type Cell = Ciphertext;
+
+    fn update_cell(&self, x: usize, y: usize, grid: &Grid<Ciphertext>) -> Ciphertext {
+        let mut count = self.server_key.create_trivial(0);
+        for neighbor in grid.neighbors(x, y) {
+            self.server_key.unchecked_add_assign(&mut count, neighbor);
+        }
+
+        // No bootstrap until here: the sum only grows into the carry bits
+        self.server_key.unchecked_scalar_mul_assign(&mut count, 2);
+        self.server_key
+            .unchecked_add_assign(&mut count, &grid[(x, y)]);
+
+        self.server_key
+            .apply_lookup_table(&count, &self.lookup_table)
+    }
+}
+
+/// The next state of a cell from its count and state packed as
+/// `2 * count + alive`, what the lookup table holds
+fn next_state(rule: Rule, packed: u64) -> u64 {
+    let (count, cell) = (packed / 2, packed % 2);
+    rule.next(cell as u8, count as u8) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::Boundary;
+    use crate::client::{self, Client};
+    use crate::keystore;
+    use crate::server::Evolve;
+
+    #[test]
+    fn table_follows_sampled_rules() {
+        let parameters = SHORTINT_PARAMETERS;
+        let space = parameters.message_modulus.0 * parameters.carry_modulus.0;
+        // Eight neighbors and a live cell, the largest packed value
+        assert!(
+            2 * 8 + 1 < space,
+            "packed values overflow the {space} plaintexts"
+        );
+        // Squared 2-norm of eight neighbors doubled plus the cell
+        let norm_squared = 8 * 2 * 2 + 1;
+        let noise = parameters.max_noise_level.get();
+        assert!(
+            norm_squared <= noise * noise,
+            "packing exceeds the noise level {noise}"
+        );
+
+        for rule in Rule::sample(64) {
+            for count in 0..=8 {
+                for cell in 0..=1 {
+                    assert_eq!(
+                        next_state(rule, 2 * count + cell),
+                        rule.next(cell as u8, count as u8) as u64,
+                        "{rule} with {count} neighbors and cell {cell}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// The smallest of `TEST_CASES`, the harness runs the others
+    #[test]
+    fn server_passes_the_first_test_case() {
+        let (m, n, steps, ..) = crate::TEST_CASES[0];
+        let rule = Rule::default();
+        let grid = client::random_grid(m, n, 0);
+        let client = Client::with_keys(grid, rule, Boundary::Dead, keystore::shortint_test_keys());
+
+        let (server_key, grid) = client.encrypt::<Ciphertext>();
+        let server = ShortintServer::new(server_key, grid, rule, 1);
+        let verification = client.verify(server.run(steps), steps);
+        assert!(verification.passed(), "{verification}");
+    }
+}
@@ -0,0 +1,93 @@
+use crate::grid::Grid;
+use std::fmt;
+
+/// A cell whose decrypted state differs from the client's simulation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Mismatch {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) expected: u8,
+    pub(crate) decrypted: u8,
+}
+
+/// Outcome of checking a server's result against the client's simulation
+#[derive(Clone, Debug)]
+pub(crate) struct Verification {
+    pub(crate) expected: Grid<u8>,
+    pub(crate) decrypted: Grid<u8>,
+    pub(crate) mismatches: Vec<Mismatch>,
+    /// First generation at which the server's grid differs from the
+    /// simulation, only known when every generation was checked
+    pub(crate) diverged_at: Option<u32>,
+}
+
+impl Verification {
+    pub(crate) fn new(expected: Grid<u8>, decrypted: Grid<u8>) -> Self {
+        let n = expected.dimensions().1;
+        let mismatches = expected
+            .cells()
+            .iter()
+            .zip(decrypted.cells())
+            .enumerate()
+            .filter(|(_, (expected, decrypted))| expected != decrypted)
+            .map(|(k, (&expected, &decrypted))| Mismatch {
+                x: k / n,
+                y: k % n,
+                expected,
+                decrypted,
+            })
+            .collect();
+
+        Verification {
+            expected,
+            decrypted,
+            mismatches,
+            diverged_at: None,
+        }
+    }
+
+    pub(crate) fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// A side-by-side diff of the expected and decrypted grids, followed by
+/// the list of mismatched cells
+///
+/// Dead cells are `.`, live cells `O` and anything else that decrypted to
+/// neither 0 nor 1 is `?`. The third grid marks mismatches with `X`.
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = |cell: u8| match cell {
+            0 => '.',
+            1 => 'O',
+            _ => '?',
+        };
+        let width = self.expected.dimensions().1.max("decrypted".len());
+
+        writeln!(f, "{:width$}   {:width$}   diff", "expected", "decrypted")?;
+        for (expected_row, decrypted_row) in self.expected.rows().zip(self.decrypted.rows()) {
+            let expected: String = expected_row.iter().map(|&cell| symbol(cell)).collect();
+            let decrypted: String = decrypted_row.iter().map(|&cell| symbol(cell)).collect();
+            let diff: String = expected_row
+                .iter()
+                .zip(decrypted_row)
+                .map(|(a, b)| if a == b { '.' } else { 'X' })
+                .collect();
+            writeln!(f, "{expected:width$}   {decrypted:width$}   {diff}")?;
+        }
+
+        write!(f, "{} mismatched cells", self.mismatches.len())?;
+        if let Some(generation) = self.diverged_at {
+            write!(f, ", first diverging at generation {generation}")?;
+        }
+        for mismatch in &self.mismatches {
+            write!(
+                f,
+                "\n  ({}, {}): expected {}, decrypted {}",
+                mismatch.x, mismatch.y, mismatch.expected, mismatch.decrypted
+            )?;
+        }
+        Ok(())
+    }
+}